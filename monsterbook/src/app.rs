@@ -1,6 +1,14 @@
+use super::arrange::{Arrangement, GroupBy, SortBy};
+use super::calibrate::Calibration;
 use super::crop::{imsave, Image};
-use super::utils;
+use super::metadata::{self, Metadata};
+use super::palette::{Palette, THEMES};
+use super::preflight::{self, PageCheck};
+use super::stitch::{Alignment, Limits, Order, StitchOptions};
+use super::upscale::{self, Scaler, Upscale};
+use super::utils::{self, CardStyle};
 use eframe::{egui, epi};
+use image::Rgba;
 use rfd::FileDialog;
 use std::sync::mpsc::Receiver;
 use std::thread;
@@ -8,14 +16,78 @@ use std::thread;
 #[derive(Default)]
 pub struct App {
     picked_path: Option<String>,
-    cropped: Option<Vec<Image>>,
-    crop_in_progress: Option<Receiver<Vec<Image>>>,
-    stitched: Option<(Image, String)>,
-    stitch_in_progress: Option<Receiver<(Image, String)>>,
+    cropped: Option<(Vec<Image>, PageCheck)>,
+    crop_in_progress: Option<Receiver<(Vec<Image>, PageCheck, Option<String>)>>,
+    file_date: Option<String>,
+    stitched: Option<(Vec<Image>, String)>,
+    stitch_in_progress: Option<Receiver<(Vec<Image>, String)>>,
+    part: usize,
     cards_per_row: u32,
+    skip_duplicates: bool,
+    fill_missing: bool,
+    gutter: u32,
+    margin: u32,
+    border_width: u32,
+    border_color: egui::Color32,
+    background: egui::Color32,
+    center_last_row: bool,
+    column_major: bool,
+    arrangement: Arrangement,
+    theme: String,
+    // zero is no limit
+    max_height: u32,
+    max_cards: u32,
+    scale: u32,
+    pixel_art: bool,
     tex_mngr: TexMngr,
 }
 
+// egui keeps colors with premultiplied alpha, while images are unmultiplied
+fn to_rgba(color: egui::Color32) -> Rgba<u8> {
+    Rgba(egui::Rgba::from(color).to_srgba_unmultiplied())
+}
+
+impl App {
+    fn upscale(&self) -> Upscale {
+        Upscale {
+            factor: self.scale,
+            scaler: if self.pixel_art {
+                Scaler::Scale2x
+            } else {
+                Scaler::Nearest
+            },
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_width: None,
+            max_height: Some(self.max_height).filter(|max| *max > 0),
+            max_cards: Some(self.max_cards as usize).filter(|max| *max > 0),
+        }
+    }
+
+    fn stitch_options(&self) -> StitchOptions {
+        StitchOptions {
+            gutter: self.gutter,
+            margin: self.margin,
+            border_width: self.border_width,
+            border_color: to_rgba(self.border_color),
+            background: to_rgba(self.background),
+            alignment: if self.center_last_row {
+                Alignment::Center
+            } else {
+                Alignment::Start
+            },
+            order: if self.column_major {
+                Order::ColumnMajor
+            } else {
+                Order::RowMajor
+            },
+        }
+    }
+}
+
 impl<'a> epi::App for App {
     fn name(&self) -> &str {
         "Monsterbook Stitcher"
@@ -24,12 +96,18 @@ impl<'a> epi::App for App {
     fn update(&mut self, ctx: &egui::CtxRef, frame: &epi::Frame) {
         if self.cards_per_row == 0 {
             self.cards_per_row = 30;
+            self.border_color = egui::Color32::BLACK;
+            // a common limit on the size of a texture
+            self.max_height = 8192;
+            self.scale = 1;
+            self.theme = THEMES[0].into();
         }
 
         if let Some(receiver) = &self.crop_in_progress {
-            if let Ok(data) = receiver.try_recv() {
+            if let Ok((images, check, file_date)) = receiver.try_recv() {
                 self.crop_in_progress = None;
-                self.cropped = Some(data);
+                self.cropped = Some((images, check));
+                self.file_date = file_date;
             }
         }
 
@@ -37,6 +115,7 @@ impl<'a> epi::App for App {
             if let Ok(data) = receiver.try_recv() {
                 self.stitch_in_progress = None;
                 self.stitched = Some(data);
+                self.part = 0;
             }
         }
 
@@ -60,8 +139,17 @@ impl<'a> epi::App for App {
                         let (sender, receiver) = std::sync::mpsc::channel();
                         self.crop_in_progress = Some(receiver);
                         thread::spawn(move || {
-                            let images = utils::get_cropped_images(&path).unwrap();
-                            sender.send(images).unwrap();
+                            let (paths, images): (Vec<_>, Vec<_>) =
+                                utils::get_cropped_images_with_paths(&path)
+                                    .unwrap()
+                                    .into_iter()
+                                    .unzip();
+                            let check =
+                                preflight::check_pages(&images, &Calibration::default()).unwrap();
+                            let file_date =
+                                metadata::file_date(paths.iter().map(|path| path.as_path()))
+                                    .unwrap();
+                            sender.send((images, check, file_date)).unwrap();
                         });
                     }
                 }
@@ -73,51 +161,189 @@ impl<'a> epi::App for App {
             if self.crop_in_progress.is_some() {
                 ui.label("cropping images, please wait...");
             }
-            if let Some(cropped) = &self.cropped {
-                ui.label(format!("{} images", cropped.len()));
+            if let Some((_, check)) = &self.cropped {
+                ui.label(check.to_string());
+                if !check.duplicates.is_empty() {
+                    ui.checkbox(&mut self.skip_duplicates, "Skip duplicate pages");
+                }
+                if !check.missing.is_empty() {
+                    ui.checkbox(&mut self.fill_missing, "Fill missing pages with blanks");
+                }
             }
+            // stitching with missing or duplicate pages puts cards on the wrong tab
+            let can_stitch = match &self.cropped {
+                Some((_, check)) => {
+                    (check.duplicates.is_empty() || self.skip_duplicates)
+                        && (check.missing.is_empty() || self.fill_missing)
+                }
+                None => false,
+            };
             ui.horizontal(|ui| {
                 ui.label("Cards per row");
                 ui.add(egui::Slider::new(&mut self.cards_per_row, 10..=100));
             });
+            ui.horizontal(|ui| {
+                ui.label("Gutter");
+                ui.add(egui::Slider::new(&mut self.gutter, 0..=16));
+                ui.label("Margin");
+                ui.add(egui::Slider::new(&mut self.margin, 0..=32));
+            });
+            ui.horizontal(|ui| {
+                ui.label("Border");
+                ui.add(egui::Slider::new(&mut self.border_width, 0..=4));
+                ui.color_edit_button_srgba(&mut self.border_color);
+                ui.label("Background");
+                ui.color_edit_button_srgba(&mut self.background);
+                egui::ComboBox::from_label("Theme")
+                    .selected_text(&self.theme)
+                    .show_ui(ui, |ui| {
+                        for theme in THEMES {
+                            ui.selectable_value(&mut self.theme, theme.to_string(), theme);
+                        }
+                    });
+            });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut self.center_last_row, "Center the last row");
+                ui.checkbox(&mut self.column_major, "Fill columns first");
+            });
+            ui.horizontal(|ui| {
+                ui.label("Scale");
+                ui.add(egui::Slider::new(&mut self.scale, 1..=4));
+                ui.checkbox(&mut self.pixel_art, "Smooth pixel art edges (Scale2x)");
+                if let Some((cropped, _)) = &self.cropped {
+                    if ui.button("Save page crops...").clicked() {
+                        if let Some(path) = FileDialog::new().pick_folder() {
+                            // the same scale as the stitched image, one file per page
+                            let upscale = self.upscale();
+                            for (i, page) in cropped.iter().enumerate() {
+                                let page_file = path.join(format!("{:02}.png", i));
+                                imsave(&page_file, &upscale::upscale(page, &upscale)).unwrap();
+                            }
+                        }
+                    }
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("Max height");
+                ui.add(egui::Slider::new(&mut self.max_height, 0..=16384));
+                ui.label("Cards per image");
+                ui.add(egui::Slider::new(&mut self.max_cards, 0..=414));
+            });
+            ui.horizontal(|ui| {
+                let arrangement = &mut self.arrangement;
+                let groups = [GroupBy::None, GroupBy::Tab, GroupBy::Town, GroupBy::Map];
+                egui::ComboBox::from_label("Group by")
+                    .selected_text(format!("{:?}", arrangement.group_by))
+                    .show_ui(ui, |ui| {
+                        for group_by in groups {
+                            ui.selectable_value(
+                                &mut arrangement.group_by,
+                                group_by,
+                                format!("{:?}", group_by),
+                            );
+                        }
+                    });
+                // sorting by count needs a transcription, which the gui does not make
+                egui::ComboBox::from_label("Sort by")
+                    .selected_text(format!("{:?}", arrangement.sort_by))
+                    .show_ui(ui, |ui| {
+                        for sort_by in [SortBy::Page, SortBy::TourOrder] {
+                            ui.selectable_value(
+                                &mut arrangement.sort_by,
+                                sort_by,
+                                format!("{:?}", sort_by),
+                            );
+                        }
+                    });
+            });
             ui.horizontal(|ui| {
                 if ui
                     .add_enabled(
-                        self.cropped.is_some(),
+                        can_stitch,
                         egui::Button::new("Generate stitched image..."),
                     )
                     .clicked()
                 {
-                    if let Some(cropped) = &self.cropped {
+                    if let Some((cropped, check)) = &self.cropped {
                         let (sender, receiver) = std::sync::mpsc::channel();
                         self.stitch_in_progress = Some(receiver);
                         // we have to make clones in order to move the values
                         // into a thread
                         let cards_per_row = self.cards_per_row;
-                        let path =
-                            format!("{}/{}", self.picked_path.as_ref().unwrap(), cards_per_row);
+                        let options = self.stitch_options();
+                        let arrangement = self.arrangement;
+                        let limits = self.limits();
+                        let upscale = self.upscale();
+                        let palette: Palette = self.theme.parse().unwrap();
+                        let path = format!(
+                            "{}/{}/{:?}/{:?}/{:?}/{:?}/{}",
+                            self.picked_path.as_ref().unwrap(),
+                            cards_per_row,
+                            options,
+                            arrangement,
+                            limits,
+                            upscale,
+                            self.theme
+                        );
                         let cloned = cropped.clone();
+                        let check = check.clone();
                         thread::spawn(move || {
                             // this path should be unique enough to update the current texture
-                            let image = utils::stitch_cards(&cloned, cards_per_row);
-                            sender.send((image, String::from(path))).unwrap();
+                            let pages = preflight::arrange_pages(&cloned, &check).unwrap();
+                            let parts = utils::stitch_cards(
+                                &pages,
+                                cards_per_row,
+                                &Calibration::default(),
+                                &options,
+                                &limits,
+                                &CardStyle {
+                                    palette,
+                                    arrangement,
+                                    upscale,
+                                    ..CardStyle::default()
+                                },
+                            );
+                            sender.send((parts, String::from(path))).unwrap();
                         });
                     }
                 }
-                if let Some((stitched, _)) = &self.stitched {
+                if let Some((parts, _)) = &self.stitched {
                     if ui.button("Save image").clicked() {
                         if let Some(path) =
                             FileDialog::new().add_filter("png", &["png"]).save_file()
                         {
-                            imsave(&path, stitched).unwrap();
+                            // the gui makes no transcription, so only the software
+                            // and file date are embedded
+                            let metadata = Metadata::new(None, self.file_date.clone(), None);
+                            // every part is saved, numbered when there are several
+                            for (i, part) in parts.iter().enumerate() {
+                                let part_path = utils::part_path(&path, i, parts.len());
+                                metadata::imsave(&part_path, part, &metadata).unwrap();
+                            }
+                        }
+                    }
+                    if parts.len() > 1 {
+                        if ui
+                            .add_enabled(self.part > 0, egui::Button::new("Previous"))
+                            .clicked()
+                        {
+                            self.part -= 1;
+                        }
+                        ui.label(format!("Part {} of {}", self.part + 1, parts.len()));
+                        if ui
+                            .add_enabled(self.part + 1 < parts.len(), egui::Button::new("Next"))
+                            .clicked()
+                        {
+                            self.part += 1;
                         }
                     }
                 }
             });
 
-            if let Some((stitched, path)) = &self.stitched {
-                let image = decode_image(stitched.clone()).unwrap();
+            if let Some((parts, path)) = &self.stitched {
+                let image = decode_image(parts[self.part].clone()).unwrap();
                 //let image = decode_image(self.cropped.as_ref().unwrap()[0].as_raw()).unwrap();
+                let path = format!("{}/{}", path, self.part);
                 if let Some(texture_id) = self.tex_mngr.texture(frame, &path, &image) {
                     let size = egui::Vec2::new(image.size[0] as f32, image.size[1] as f32);
                     ui.image(texture_id, size);
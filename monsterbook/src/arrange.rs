@@ -0,0 +1,155 @@
+use super::catalog;
+use super::utils;
+use std::collections::HashMap;
+use std::str::FromStr;
+
+/// Sections that cards are split into before stitching.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GroupBy {
+    #[default]
+    None,
+    Tab,
+    Town,
+    Map,
+}
+
+impl FromStr for GroupBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(GroupBy::None),
+            "tab" => Ok(GroupBy::Tab),
+            "town" => Ok(GroupBy::Town),
+            "map" => Ok(GroupBy::Map),
+            _ => Err(format!(
+                "unknown grouping {}, expected one of none, tab, town, map",
+                s
+            )),
+        }
+    }
+}
+
+/// The order of cards within a section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Page,
+    TourOrder,
+    /// Most collected first
+    Count,
+}
+
+impl FromStr for SortBy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "page" => Ok(SortBy::Page),
+            "tour-order" => Ok(SortBy::TourOrder),
+            "count" => Ok(SortBy::Count),
+            _ => Err(format!(
+                "unknown order {}, expected one of page, tour-order, count",
+                s
+            )),
+        }
+    }
+}
+
+/// The default keeps cards in page order as a single section.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Arrangement {
+    pub group_by: GroupBy,
+    pub sort_by: SortBy,
+}
+
+/// Reorder cards keyed by uid and split them into named sections. Sections
+/// appear in the order of their first card. Cards without a count sort as
+/// if none were collected.
+pub fn arrange<T>(
+    mut cards: Vec<(usize, T)>,
+    arrangement: &Arrangement,
+    counts: Option<&HashMap<usize, u8>>,
+) -> Vec<(String, Vec<T>)> {
+    let monsters = catalog::monsters();
+    let metadata = utils::page_metadata();
+    let count = |uid: usize| counts.and_then(|counts| counts.get(&uid)).copied();
+    match arrangement.sort_by {
+        SortBy::Page => cards.sort_by_key(|(uid, _)| *uid),
+        SortBy::TourOrder => cards.sort_by_key(|(uid, _)| {
+            let tour_order = monsters.get(*uid).map(|monster| monster.tour_order);
+            (tour_order.unwrap_or(u32::MAX), *uid)
+        }),
+        SortBy::Count => cards.sort_by_key(|(uid, _)| (u8::MAX - count(*uid).unwrap_or(0), *uid)),
+    }
+    let key = |uid: usize| -> String {
+        let monster = monsters.get(uid);
+        match arrangement.group_by {
+            GroupBy::None => String::new(),
+            GroupBy::Tab => utils::page_of(uid)
+                .map(|page| metadata[page].tab_color.clone())
+                .unwrap_or_default(),
+            GroupBy::Town => monster.map(|m| m.town.clone()).unwrap_or_default(),
+            GroupBy::Map => monster.map(|m| m.map.clone()).unwrap_or_default(),
+        }
+    };
+    let mut sections: Vec<(String, Vec<T>)> = Vec::new();
+    for (uid, card) in cards {
+        let key = key(uid);
+        match sections.iter_mut().find(|(name, _)| *name == key) {
+            Some((_, section)) => section.push(card),
+            None => sections.push((key, vec![card])),
+        }
+    }
+    sections
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sections_follow_their_first_card() {
+        // the first orange card follows the single red page
+        let orange = utils::page_offsets()[1];
+        let cards = vec![(orange + 1, orange + 1), (0, 0), (orange, orange)];
+        let by_tab = Arrangement {
+            group_by: GroupBy::Tab,
+            sort_by: SortBy::Page,
+        };
+        assert_eq!(
+            arrange(cards.clone(), &by_tab, None),
+            vec![
+                ("red".to_string(), vec![0]),
+                ("orange".to_string(), vec![orange, orange + 1]),
+            ]
+        );
+        let counts = HashMap::from([(0, 1), (orange, 5), (orange + 1, 3)]);
+        let by_count = Arrangement {
+            sort_by: SortBy::Count,
+            ..by_tab
+        };
+        assert_eq!(
+            arrange(cards, &by_count, Some(&counts)),
+            vec![
+                ("orange".to_string(), vec![orange, orange + 1]),
+                ("red".to_string(), vec![0]),
+            ]
+        );
+    }
+
+    #[test]
+    fn most_collected_cards_come_first() {
+        let cards = (0..5).map(|uid| (uid, uid)).collect();
+        let counts = HashMap::from([(1, 2), (2, 5), (3, 2)]);
+        let arrangement = Arrangement {
+            group_by: GroupBy::None,
+            sort_by: SortBy::Count,
+        };
+        // ties keep page order, and cards without a count count as none
+        assert_eq!(
+            arrange(cards, &arrangement, Some(&counts)),
+            vec![(String::new(), vec![2, 1, 3, 0, 4])]
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use super::crop::Image;
+use super::sprites;
+use super::stitch;
+use image::imageops;
+use serde::Serialize;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+// keeps neighbouring frames from bleeding into each other when filtered
+const PADDING: u32 = 1;
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Size {
+    pub w: u32,
+    pub h: u32,
+}
+
+/// A frame in the array layout of TexturePacker, with the uid and name of the
+/// monster alongside.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    pub filename: String,
+    pub uid: usize,
+    pub name: String,
+    pub frame: Rect,
+    pub rotated: bool,
+    pub trimmed: bool,
+    pub sprite_source_size: Rect,
+    pub source_size: Size,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Meta {
+    pub app: String,
+    pub version: String,
+    pub image: String,
+    pub format: String,
+    pub size: Size,
+    pub scale: String,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Atlas {
+    pub frames: Vec<Frame>,
+    pub meta: Meta,
+}
+
+impl Atlas {
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+}
+
+/// Crop an image to the bounding box of its visible pixels, returning the
+/// box within the original. Fully transparent images keep a single pixel.
+pub fn trim(img: &Image) -> (Image, Rect) {
+    let visible: Vec<(u32, u32)> = img
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel[3] > 0)
+        .map(|(x, y, _)| (x, y))
+        .collect();
+    let rect = if visible.is_empty() {
+        Rect {
+            x: 0,
+            y: 0,
+            w: 1,
+            h: 1,
+        }
+    } else {
+        let x = visible.iter().map(|(x, _)| *x).min().unwrap();
+        let y = visible.iter().map(|(_, y)| *y).min().unwrap();
+        let right = visible.iter().map(|(x, _)| *x).max().unwrap();
+        let bottom = visible.iter().map(|(_, y)| *y).max().unwrap();
+        Rect {
+            x,
+            y,
+            w: right - x + 1,
+            h: bottom - y + 1,
+        }
+    };
+    let mut img = img.clone();
+    let trimmed = imageops::crop(&mut img, rect.x, rect.y, rect.w, rect.h).to_image();
+    (trimmed, rect)
+}
+
+/// Pack images of monsters into a single texture, ordered by uid. Rows are
+/// as wide as a square with the total area of the images.
+pub fn build(
+    mut entries: Vec<(usize, String, Image)>,
+    trim_frames: bool,
+    image: &str,
+) -> (Image, Atlas) {
+    entries.sort_by_key(|(uid, _, _)| *uid);
+    let (images, rects): (Vec<Image>, Vec<Rect>) = entries
+        .iter()
+        .map(|(_, _, img)| {
+            if trim_frames {
+                trim(img)
+            } else {
+                let rect = Rect {
+                    x: 0,
+                    y: 0,
+                    w: img.width(),
+                    h: img.height(),
+                };
+                (img.clone(), rect)
+            }
+        })
+        .unzip();
+    let area: u32 = images
+        .iter()
+        .map(|img| (img.width() + PADDING) * (img.height() + PADDING))
+        .sum();
+    let widest = images.iter().map(|img| img.width()).max().unwrap_or(0);
+    let width = ((area as f64).sqrt().ceil() as u32).max(widest);
+    let (texture, positions) = stitch::pack_images(&images, width, PADDING);
+    let frames = entries
+        .iter()
+        .zip(images.iter().zip(rects))
+        .zip(positions)
+        .map(|(((uid, name, original), (img, rect)), (x, y))| Frame {
+            filename: sprites::sprite_name(*uid, name),
+            uid: *uid,
+            name: name.clone(),
+            frame: Rect {
+                x,
+                y,
+                w: img.width(),
+                h: img.height(),
+            },
+            rotated: false,
+            trimmed: rect.w != original.width() || rect.h != original.height(),
+            sprite_source_size: rect,
+            source_size: Size {
+                w: original.width(),
+                h: original.height(),
+            },
+        })
+        .collect();
+    let meta = Meta {
+        app: env!("CARGO_PKG_NAME").into(),
+        version: env!("CARGO_PKG_VERSION").into(),
+        image: image.into(),
+        format: "RGBA8888".into(),
+        size: Size {
+            w: texture.width(),
+            h: texture.height(),
+        },
+        scale: "1".into(),
+    };
+    (texture, Atlas { frames, meta })
+}
@@ -0,0 +1,230 @@
+use super::calibrate::Calibration;
+use super::crop::{self, Image};
+use super::occlusion::Occlusion;
+use super::transcribe::{self, TranscribedPage};
+use super::transcription::Entry;
+use image::ImageError;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The platform's cache directory for this tool, or `None` when the
+/// environment does not name one. Nothing is created until there is an entry
+/// to store.
+pub fn default_dir() -> Option<PathBuf> {
+    let base = if cfg!(windows) {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|home| Path::new(&home).join("Library/Caches"))
+    } else {
+        // relative paths are invalid according to the xdg spec
+        env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+    };
+    Some(base?.join("monsterbook"))
+}
+
+/// FNV-1a of the file contents, which unlike the std hasher is stable between
+/// builds.
+pub fn content_hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+/// The result of transcribing a page, only valid for the same calibration.
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CachedTranscription {
+    pub calibration: String,
+    pub page_id: usize,
+    pub slots: Vec<usize>,
+    pub entries: Vec<Entry>,
+    pub uncertain: Vec<usize>,
+    pub occluded: Vec<(usize, Occlusion)>,
+}
+
+#[derive(Clone, Deserialize, Serialize)]
+pub struct CachedPage {
+    pub offset: (u32, u32),
+    pub transcription: Option<CachedTranscription>,
+}
+
+/// Processed screenshots keyed by the hash of their contents. Each entry is a
+/// json file with the offsets and transcription, next to the cropped page.
+pub struct Cache {
+    dir: Option<PathBuf>,
+}
+
+impl Cache {
+    pub fn new(dir: &Path) -> Self {
+        Cache {
+            dir: Some(dir.into()),
+        }
+    }
+
+    /// A cache that never stores anything, for `--no-cache`.
+    pub fn disabled() -> Self {
+        Cache { dir: None }
+    }
+
+    fn path(&self, key: &str, extension: &str) -> Option<PathBuf> {
+        let mut path = self.dir.clone()?;
+        path.push(format!("{}.{}", key, extension));
+        Some(path)
+    }
+
+    // entries that cannot be read are treated as missing and written again
+    pub fn get(&self, key: &str) -> Option<CachedPage> {
+        let file = File::open(self.path(key, "json")?).ok()?;
+        serde_json::from_reader(file).ok()
+    }
+
+    pub fn put(&self, key: &str, page: &CachedPage) -> io::Result<()> {
+        if let Some(path) = self.path(key, "json") {
+            fs::create_dir_all(path.parent().unwrap())?;
+            serde_json::to_writer(File::create(path)?, page)?;
+        }
+        Ok(())
+    }
+
+    pub fn image(&self, key: &str) -> Option<Image> {
+        crop::imread(&self.path(key, "png")?).ok()
+    }
+
+    pub fn put_image(&self, key: &str, img: &Image) -> Result<(), ImageError> {
+        if let Some(path) = self.path(key, "png") {
+            fs::create_dir_all(path.parent().unwrap())?;
+            crop::imsave(&path, img)?;
+        }
+        Ok(())
+    }
+
+    /// Remove every entry whose key is not in `keep`, returning how many
+    /// screenshots were forgotten.
+    pub fn prune(&self, keep: &HashSet<String>) -> io::Result<usize> {
+        let dir = match &self.dir {
+            Some(dir) if dir.is_dir() => dir,
+            _ => return Ok(0),
+        };
+        let mut removed = 0;
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let key = path.file_stem().unwrap_or_default().to_string_lossy();
+            if keep.contains(key.as_ref()) {
+                continue;
+            }
+            if path.extension() == Some("json".as_ref()) {
+                removed += 1;
+            }
+            fs::remove_file(&path)?;
+        }
+        Ok(removed)
+    }
+}
+
+pub struct Screenshot {
+    pub path: PathBuf,
+    pub key: String,
+    pub image: Image,
+}
+
+/// The keys of every screenshot in a directory, without decoding them.
+pub fn keys(source: &Path) -> io::Result<Vec<String>> {
+    fs::read_dir(source)?
+        .map(|entry| Ok(content_hash(&fs::read(entry?.path())?)))
+        .collect()
+}
+
+/// Crop every screenshot in a directory like
+/// `utils::get_cropped_images_with_paths`, only decoding and localizing the
+/// screenshots that are not in the cache.
+pub fn cropped_images(source: &Path, cache: &Cache) -> Result<Vec<Screenshot>, ImageError> {
+    let mut screenshots = Vec::new();
+    let mut offset = None;
+    for entry in fs::read_dir(source)? {
+        let path = entry?.path();
+        let bytes = fs::read(&path)?;
+        let key = content_hash(&bytes);
+        if let (Some(cached), Some(image)) = (cache.get(&key), cache.image(&key)) {
+            offset.get_or_insert(cached.offset);
+            screenshots.push(Screenshot { path, key, image });
+            continue;
+        }
+        let mut img = crop::imdecode(&bytes)?;
+        let (x, y) = match offset {
+            Some(offset) => offset,
+            None => crop::match_reference_page(&img)?,
+        };
+        offset = Some((x, y));
+        let image = crop::crop(&mut img, x, y)?;
+        cache.put_image(&key, &image)?;
+        cache.put(
+            &key,
+            &CachedPage {
+                offset: (x, y),
+                transcription: None,
+            },
+        )?;
+        screenshots.push(Screenshot { path, key, image });
+    }
+    Ok(screenshots)
+}
+
+/// Transcribe cropped screenshots like `transcribe::transcribe_pages`, reusing
+/// the page ids and counts of screenshots seen with the same calibration.
+pub fn transcribe_pages(
+    screenshots: &[Screenshot],
+    calibration: &Calibration,
+    cache: &Cache,
+) -> Result<Vec<TranscribedPage>, ImageError> {
+    let calibration_key = content_hash(&serde_json::to_vec(calibration).unwrap());
+    let references = transcribe::reference_pages()?;
+    let tags = transcribe::seed_tags()?;
+    screenshots
+        .iter()
+        .map(|screenshot| {
+            let cached = cache.get(&screenshot.key);
+            let transcription = cached
+                .as_ref()
+                .and_then(|cached| cached.transcription.as_ref())
+                .filter(|transcription| transcription.calibration == calibration_key);
+            if let Some(transcription) = transcription {
+                let cards = crop::crop_cards(&screenshot.image)?;
+                return Ok(TranscribedPage {
+                    page_id: transcription.page_id,
+                    cards: transcription
+                        .entries
+                        .iter()
+                        .zip(&transcription.slots)
+                        .map(|(entry, slot)| (entry.clone(), cards[*slot].clone()))
+                        .collect(),
+                    slots: transcription.slots.clone(),
+                    uncertain: transcription.uncertain.clone(),
+                    occluded: transcription.occluded.clone(),
+                });
+            }
+            let page =
+                transcribe::transcribe_page(&screenshot.image, &references, &tags, calibration)?;
+            if let Some(mut cached) = cached {
+                cached.transcription = Some(CachedTranscription {
+                    calibration: calibration_key.clone(),
+                    page_id: page.page_id,
+                    slots: page.slots.clone(),
+                    entries: page.cards.iter().map(|(entry, _)| entry.clone()).collect(),
+                    uncertain: page.uncertain.clone(),
+                    occluded: page.occluded.clone(),
+                });
+                cache.put(&screenshot.key, &cached)?;
+            }
+            Ok(page)
+        })
+        .collect()
+}
@@ -0,0 +1,292 @@
+use super::crop::{self, CardClassifier, CardState, Image};
+use super::metrics::MetricKind;
+use super::quality;
+use super::transcribe;
+use super::transcription::Transcription;
+use super::utils;
+use image::{ImageError, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+/// Thresholds used by the stitching and transcription commands.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Calibration {
+    #[serde(flatten)]
+    pub cards: CardClassifier,
+    /// Tags further than this from every seed tag are flagged as uncertain
+    #[serde(default)]
+    pub tag: Option<u32>,
+    /// The metric used to identify pages against the reference book
+    #[serde(default)]
+    pub page_metric: MetricKind,
+    /// The metric used to match tags against the seed tags
+    #[serde(default)]
+    pub tag_metric: MetricKind,
+    /// Denoise pages before comparing them, for heavily compressed screenshots
+    #[serde(default)]
+    pub denoise: bool,
+}
+
+impl Default for Calibration {
+    fn default() -> Self {
+        serde_json::from_str(include_str!("assets/thresholds.json")).unwrap()
+    }
+}
+
+impl Calibration {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+
+    /// A page as the thresholds expect it, denoised when they were calibrated
+    /// on denoised pages.
+    pub fn compared(&self, page: &Image) -> Image {
+        if self.denoise {
+            quality::denoise(page)
+        } else {
+            page.clone()
+        }
+    }
+}
+
+/// Distances collected from a set of cropped pages.
+pub struct Distributions {
+    pub card_metric: MetricKind,
+    pub tag_metric: MetricKind,
+    /// The distance of every card from the empty card
+    pub cards: Vec<u32>,
+    /// The distance of every seen card's tag from its closest seed tag
+    pub tags: Vec<u32>,
+    /// The state of every card, when the pages were labelled
+    pub labels: Option<Vec<CardState>>,
+}
+
+/// Label every card on the pages from a trusted transcription of the same
+/// screenshots. Slots past the end of a page are empty, cards with a count are
+/// seen and the rest are unseen.
+pub fn labels(
+    images: &[Image],
+    transcription: &Transcription,
+    page_metric: MetricKind,
+) -> Result<Vec<CardState>, ImageError> {
+    let references = transcribe::reference_pages()?;
+    let counts = transcription.counts();
+    let offsets = utils::page_offsets();
+    let metadata = utils::page_metadata();
+    let mut labels = Vec::new();
+    for img in images {
+        let page_id = transcribe::best_match(img, &references, page_metric);
+        for slot in 0..crop::crop_cards(img)?.len() {
+            let count = counts.get(&(offsets[page_id] + slot)).copied().unwrap_or(0);
+            labels.push(if slot >= metadata[page_id].card_count as usize {
+                CardState::Empty
+            } else if count > 0 {
+                CardState::Seen
+            } else {
+                CardState::Unseen
+            });
+        }
+    }
+    Ok(labels)
+}
+
+pub fn distributions(
+    images: &[Image],
+    card_metric: MetricKind,
+    tag_metric: MetricKind,
+    labels: Option<Vec<CardState>>,
+) -> Result<Distributions, ImageError> {
+    let mut cards = Vec::new();
+    for img in images {
+        cards.extend(crop::crop_cards(img)?);
+    }
+    let card_distances: Vec<u32> = cards
+        .iter()
+        .map(|card| crop::card_distance(card, card_metric))
+        .collect();
+    // tags only exist on seen cards, which we pick out with the labels or the
+    // suggested cuts
+    let states = match &labels {
+        Some(labels) => labels.clone(),
+        None => {
+            let classifier = CardClassifier::from_distances(&card_distances, card_metric);
+            card_distances
+                .iter()
+                .map(|distance| classifier.classify_distance(*distance))
+                .collect()
+        }
+    };
+    let seeds = transcribe::seed_tags()?;
+    let tag_distances = cards
+        .iter()
+        .zip(&states)
+        .filter(|(_, state)| **state == CardState::Seen)
+        .map(|(card, _)| {
+            let tag = transcribe::crop_tag(card);
+            transcribe::best_match_distance(&tag, &seeds, tag_metric).1
+        })
+        .collect();
+    Ok(Distributions {
+        card_metric,
+        tag_metric,
+        cards: card_distances,
+        tags: tag_distances,
+        labels,
+    })
+}
+
+/// Suggest thresholds from the distributions. Card cuts come from Otsu's
+/// method, fitted to the labels when there are any, while tags are cut three
+/// standard deviations above the mean.
+pub fn suggest(distributions: &Distributions, page_metric: MetricKind) -> Calibration {
+    let tags = &distributions.tags;
+    let tag = if tags.is_empty() {
+        None
+    } else {
+        let n = tags.len() as f64;
+        let mean = tags.iter().map(|x| *x as f64).sum::<f64>() / n;
+        let variance = tags.iter().map(|x| (*x as f64 - mean).powi(2)).sum::<f64>() / n;
+        Some((mean + 3.0 * variance.sqrt()).ceil() as u32)
+    };
+    Calibration {
+        cards: match &distributions.labels {
+            Some(labels) => {
+                let samples: Vec<(u32, CardState)> = distributions
+                    .cards
+                    .iter()
+                    .copied()
+                    .zip(labels.iter().copied())
+                    .collect();
+                CardClassifier::fit(&samples, distributions.card_metric)
+            }
+            None => CardClassifier::from_distances(&distributions.cards, distributions.card_metric),
+        },
+        tag,
+        page_metric,
+        tag_metric: distributions.tag_metric,
+        denoise: false,
+    }
+}
+
+/// Parse a number of histogram bins, which has to be at least one.
+pub fn parse_bins(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("the number of bins has to be at least 1".into()),
+        Ok(bins) => Ok(bins),
+        Err(_) => Err(format!("invalid number of bins {}", s)),
+    }
+}
+
+pub struct Histogram {
+    pub min: u32,
+    pub bin_width: u32,
+    pub counts: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn new(values: &[u32], bins: u32) -> Self {
+        let min = values.iter().copied().min().unwrap_or(0);
+        let max = values.iter().copied().max().unwrap_or(0);
+        let bin_width = ((max - min) / bins + 1).max(1);
+        let mut counts = vec![0; bins as usize];
+        for value in values {
+            counts[((value - min) / bin_width) as usize] += 1;
+        }
+        Histogram {
+            min,
+            bin_width,
+            counts,
+        }
+    }
+
+    fn bin(&self, value: u32) -> Option<usize> {
+        let index = (value.checked_sub(self.min)? / self.bin_width) as usize;
+        if index < self.counts.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Render as one line per bin, marking the bins that contain a cut.
+    pub fn format_ascii(&self, cuts: &[u32], width: u32) -> String {
+        let max = *self.counts.iter().max().unwrap_or(&1).max(&1);
+        let cut_bins: Vec<usize> = cuts.iter().filter_map(|cut| self.bin(*cut)).collect();
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, count)| {
+                let start = self.min + i as u32 * self.bin_width;
+                format!(
+                    "{:>6} {} {:<width$} {}",
+                    start,
+                    if cut_bins.contains(&i) { ">" } else { "|" },
+                    "#".repeat((count * width / max) as usize),
+                    count,
+                    width = width as usize
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Render as a bar chart, with cuts drawn as red lines.
+    pub fn render(&self, cuts: &[u32]) -> Image {
+        let (bar_width, height) = (8, 200);
+        let max = *self.counts.iter().max().unwrap_or(&1).max(&1);
+        let width = bar_width * self.counts.len() as u32;
+        let mut img = RgbaImage::from_pixel(width, height, Rgba([255, 255, 255, 255]));
+        for (i, count) in self.counts.iter().enumerate() {
+            let bar_height = count * (height - 1) / max;
+            for x in i as u32 * bar_width..(i as u32 + 1) * bar_width - 1 {
+                for y in height - bar_height..height {
+                    img.put_pixel(x, y, Rgba([64, 64, 64, 255]));
+                }
+            }
+        }
+        for cut in cuts {
+            if cut < &self.min {
+                continue;
+            }
+            let x = (cut - self.min) * bar_width / self.bin_width;
+            if x < width {
+                for y in 0..height {
+                    img.put_pixel(x, y, Rgba([255, 0, 0, 255]));
+                }
+            }
+        }
+        img
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn histogram_covers_every_value() {
+        let histogram = Histogram::new(&[10, 12, 15, 20, 29], 4);
+        assert_eq!(histogram.min, 10);
+        assert_eq!(histogram.bin_width, 5);
+        assert_eq!(histogram.counts, vec![2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn histogram_of_equal_values() {
+        let histogram = Histogram::new(&[7, 7, 7], 3);
+        assert_eq!(histogram.bin_width, 1);
+        assert_eq!(histogram.counts, vec![3, 0, 0]);
+    }
+
+    #[test]
+    fn zero_bins_are_rejected() {
+        assert!(parse_bins("0").is_err());
+        assert!(parse_bins("x").is_err());
+        assert_eq!(parse_bins("40"), Ok(40));
+    }
+}
@@ -0,0 +1,20 @@
+use serde::Deserialize;
+
+// generated by `node scripts/generate_monsters_json.mjs`
+const MONSTERS_JSON: &str = include_str!("assets/monsters.json");
+/// Bump whenever a regenerated catalog moves cards to different uids, so that
+/// share codes made against the old order are rejected.
+pub const VERSION: u8 = 1;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Monster {
+    pub name: String,
+    pub map: String,
+    pub town: String,
+    pub tour_order: u32,
+}
+
+/// The monster catalog, indexed by uid in book order.
+pub fn monsters() -> Vec<Monster> {
+    serde_json::from_str(MONSTERS_JSON).unwrap()
+}
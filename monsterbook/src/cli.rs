@@ -1,15 +1,38 @@
 extern crate clap;
 
-use clap::{AppSettings, Parser, Subcommand};
-use monsterbook::{crop, stitch, utils};
+use clap::{AppSettings, Args, Parser, Subcommand};
+use image::{ImageError, Rgba};
+use monsterbook::arrange::{Arrangement, GroupBy, SortBy};
+use monsterbook::cache::{self, Cache, Screenshot};
+use monsterbook::calibrate::{self, Calibration, Histogram};
+use monsterbook::crop::Image;
+use monsterbook::diff::CardChange;
+use monsterbook::labels::{self, Caption, Labels};
+use monsterbook::metadata::{self, Metadata};
+use monsterbook::metrics::MetricKind;
+use monsterbook::overlay::{Incomplete, Overlay};
+use monsterbook::palette::Palette;
+use monsterbook::recognize::CardIndex;
+use monsterbook::stitch::{Alignment, Limits, Order, StitchOptions};
+use monsterbook::transcription::{Transcription, MAX_COUNT};
+use monsterbook::upscale::{self, Scaler, Upscale};
+use monsterbook::utils::CardStyle;
+use monsterbook::{
+    atlas, crop, diff, merge, missing, plan, preflight, quality, recognize, share, sprites, stats,
+    stitch, transcribe, utils,
+};
+use std::collections::{HashMap, HashSet};
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser)]
 #[clap(name = "monsterbook")]
 struct Cli {
     #[clap(subcommand)]
     command: Commands,
+    /// Process every screenshot again instead of reading the cache
+    #[clap(long = "no-cache", global = true, parse(from_flag))]
+    no_cache: bool,
 }
 
 #[derive(Subcommand)]
@@ -21,6 +44,8 @@ enum Commands {
         source: PathBuf,
         #[clap(required = true, parse(from_os_str))]
         output: PathBuf,
+        #[clap(flatten)]
+        scaling: Scaling,
     },
     /// Crop cards from a single screenshot
     #[clap(setting(AppSettings::ArgRequiredElseHelp))]
@@ -29,6 +54,17 @@ enum Commands {
         source: PathBuf,
         #[clap(required = true, parse(from_os_str))]
         output: PathBuf,
+        #[clap(flatten)]
+        scaling: Scaling,
+    },
+    /// Classify the cards from a single screenshot as empty, unseen or seen
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    ClassifyCards {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        /// Thresholds to use instead of the defaults
+        #[clap(long, parse(from_os_str))]
+        thresholds: Option<PathBuf>,
     },
     /// Generate the reference pages with the appropriate filenames
     #[clap(setting(AppSettings::ArgRequiredElseHelp))]
@@ -45,6 +81,14 @@ enum Commands {
         source: PathBuf,
         #[clap(required = true, parse(from_os_str))]
         output: PathBuf,
+        /// Keep the first screenshot of pages that were captured more than once
+        #[clap(long = "skip-duplicates", parse(from_flag))]
+        skip_duplicates: bool,
+        /// Use blank pages for pages that were not captured
+        #[clap(long = "fill-missing", parse(from_flag))]
+        fill_missing: bool,
+        #[clap(flatten)]
+        layout: Layout,
     },
     /// Create a stitched image of cards
     #[clap(setting(AppSettings::ArgRequiredElseHelp))]
@@ -55,21 +99,478 @@ enum Commands {
         output: PathBuf,
         #[clap(long = "generate-stats", parse(from_flag))]
         generate_stats: bool,
+        /// Keep the first screenshot of pages that were captured more than once
+        #[clap(long = "skip-duplicates", parse(from_flag))]
+        skip_duplicates: bool,
+        /// Use blank pages for pages that were not captured
+        #[clap(long = "fill-missing", parse(from_flag))]
+        fill_missing: bool,
+        /// Thresholds written by the calibrate command
+        #[clap(long, parse(from_os_str))]
+        thresholds: Option<PathBuf>,
+        /// Card backgrounds, one of in-game, pastel or colorblind
+        #[clap(long, default_value = "in-game")]
+        theme: Palette,
+        /// Theme file to use instead of a named theme
+        #[clap(long, parse(from_os_str))]
+        palette: Option<PathBuf>,
+        #[clap(flatten)]
+        layout: Layout,
+        #[clap(flatten)]
+        scaling: Scaling,
+        /// Character name for a header with the date and completion
+        #[clap(long)]
+        title: Option<String>,
+        /// Split cards into sections, one of none, tab, town or map
+        #[clap(long = "group-by", default_value = "none")]
+        group_by: GroupBy,
+        /// Order of cards within a section, one of page, tour-order or count
+        #[clap(long = "sort-by", default_value = "page")]
+        sort_by: SortBy,
+        /// Start each section with its name, grouping by tab unless grouped otherwise
+        #[clap(long, alias = "tab-headings", parse(from_flag))]
+        headings: bool,
+        /// Text below each card, one of name or uid
+        #[clap(long)]
+        captions: Option<Caption>,
+        /// Draw the count of each card in its corner
+        #[clap(long, parse(from_flag))]
+        badges: bool,
+        /// How to draw cards short of a full set, one of keep, dim or grayscale
+        #[clap(long, default_value = "keep")]
+        incomplete: Incomplete,
+        /// Tint each card from red to green by its count
+        #[clap(long, parse(from_flag))]
+        heatmap: bool,
+        /// Transcription to take counts from instead of the screenshots
+        #[clap(long, parse(from_os_str))]
+        counts: Option<PathBuf>,
+        /// Keep the transcription, character name and screenshot file date in the png
+        #[clap(long, parse(from_flag))]
+        embed: bool,
+    },
+    /// Write the artwork of every seen card with a transparent background
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    ExtractSprites {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
+        /// Thresholds written by the calibrate command
+        #[clap(long, parse(from_os_str))]
+        thresholds: Option<PathBuf>,
+    },
+    /// Pack the sprites of every seen card into one texture with a json frame map
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Atlas {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        /// The texture, the frame map is written next to it with a json extension
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
+        /// Pack whole cards instead of sprites
+        #[clap(long = "cards", parse(from_flag))]
+        cards: bool,
+        /// Thresholds written by the calibrate command
+        #[clap(long, parse(from_os_str))]
+        thresholds: Option<PathBuf>,
+    },
+    /// Suggest thresholds from the distance distributions of a directory of screenshots
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Calibrate {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        /// Write the suggested thresholds to a file
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+        /// Directory to write the histograms as images
+        #[clap(long, parse(from_os_str))]
+        plots: Option<PathBuf>,
+        #[clap(long, default_value = "40", parse(try_from_str = calibrate::parse_bins))]
+        bins: u32,
+        /// Metric used to identify pages, one of mse, ncc, ssim or sobel
+        #[clap(long = "page-metric", default_value = "mse")]
+        page_metric: MetricKind,
+        /// Metric used to compare cards against the empty card
+        #[clap(long = "card-metric", default_value = "mse")]
+        card_metric: MetricKind,
+        /// Metric used to compare tags against the seed tags
+        #[clap(long = "tag-metric", default_value = "mse")]
+        tag_metric: MetricKind,
+        /// Transcription of the screenshots, used to label each card instead
+        /// of guessing the states from the distances
+        #[clap(long, parse(from_os_str))]
+        labels: Option<PathBuf>,
+        /// Denoise pages before comparing them, recorded in the thresholds
+        #[clap(long = "denoise", parse(from_flag))]
+        denoise: bool,
+    },
+    /// Report the jpeg quality and blockiness of a directory of screenshots
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Quality {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+    },
+    /// Plan a farming route from a transcription
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Plan {
+        #[clap(required = true, parse(from_os_str))]
+        transcription: PathBuf,
+    },
+    /// Show completion statistics from a transcription
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Stats {
+        #[clap(required = true, parse(from_os_str))]
+        transcription: PathBuf,
+        #[clap(long = "json", parse(from_flag))]
+        json: bool,
+    },
+    /// Create a checklist image of the cards that are unseen or incomplete
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Missing {
+        /// A transcription or a directory of screenshots
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
+        #[clap(long, default_value = "4")]
+        columns: u32,
+        #[clap(flatten)]
+        layout: Layout,
+        #[clap(flatten)]
+        scaling: Scaling,
+        /// Character name for a header with the number of missing cards
+        #[clap(long)]
+        title: Option<String>,
+        /// Split cards into sections, one of none, tab, town or map
+        #[clap(long = "group-by", default_value = "none")]
+        group_by: GroupBy,
+        /// Order of cards within a section, one of page, tour-order or count
+        #[clap(long = "sort-by", default_value = "page")]
+        sort_by: SortBy,
+        /// Start each section with its name
+        #[clap(long, parse(from_flag))]
+        headings: bool,
+        /// Keep the transcription, character name and screenshot file date in the png
+        #[clap(long, parse(from_flag))]
+        embed: bool,
+    },
+    /// Show what a png written with --embed was made from
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Inspect {
+        #[clap(required = true, parse(from_os_str))]
+        image: PathBuf,
+        /// Write the embedded transcription to a file
+        #[clap(long, parse(from_os_str))]
+        transcription: Option<PathBuf>,
+        #[clap(long = "json", parse(from_flag))]
+        json: bool,
+    },
+    /// Transcribe a directory of screenshots
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Transcribe {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
+        /// Thresholds written by the calibrate command
+        #[clap(long, parse(from_os_str))]
+        thresholds: Option<PathBuf>,
+        /// Denoise pages before comparing them
+        #[clap(long = "denoise", parse(from_flag))]
+        denoise: bool,
+        /// Recognize each card from its artwork and flag cards whose position disagrees
+        #[clap(long = "cross-check", parse(from_flag))]
+        cross_check: bool,
+        /// Screenshots of pages that were checked by hand, added to the recognition index
+        #[clap(long, parse(from_os_str))]
+        reference: Option<PathBuf>,
+    },
+    /// Manage the cache of processed screenshots
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Cache {
+        #[clap(subcommand)]
+        command: CacheCommands,
+    },
+    /// Turn a book into a short code to paste into chat or the web app, and back
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Share {
+        #[clap(subcommand)]
+        command: ShareCommands,
+    },
+    /// Compare two transcriptions or screenshot directories of the same character
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Diff {
+        #[clap(required = true, parse(from_os_str))]
+        before: PathBuf,
+        #[clap(required = true, parse(from_os_str))]
+        after: PathBuf,
+        /// Stitch the changed cards, only when `after` is a screenshot directory
+        #[clap(long, parse(from_os_str))]
+        image: Option<PathBuf>,
+    },
+    /// Merge several capture sessions, keeping the latest capture of each page
+    #[clap(setting(AppSettings::ArgRequiredElseHelp))]
+    Merge {
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
+        /// Transcription files or screenshot directories
+        #[clap(required = true, parse(from_os_str))]
+        sources: Vec<PathBuf>,
+        /// Write a stitched image of the merged cards
+        #[clap(long, parse(from_os_str))]
+        image: Option<PathBuf>,
+    },
+}
+
+/// Layout of stitched images
+#[derive(Args)]
+struct Layout {
+    /// Space between images
+    #[clap(long, default_value = "0")]
+    gutter: u32,
+    /// Space around the outside of the image
+    #[clap(long, default_value = "0")]
+    margin: u32,
+    #[clap(long = "border-width", default_value = "0")]
+    border_width: u32,
+    /// Color as rrggbb or rrggbbaa
+    #[clap(long = "border-color", default_value = "000000", parse(try_from_str = stitch::parse_color))]
+    border_color: Rgba<u8>,
+    /// Color as rrggbb or rrggbbaa, transparent by default
+    #[clap(long, default_value = "00000000", parse(try_from_str = stitch::parse_color))]
+    background: Rgba<u8>,
+    /// Alignment of the last row, one of start, center or end
+    #[clap(long, default_value = "start")]
+    align: Alignment,
+    /// Fill columns before rows
+    #[clap(long = "column-major", parse(from_flag))]
+    column_major: bool,
+    /// Split into numbered parts no wider than this many pixels
+    #[clap(long = "max-width")]
+    max_width: Option<u32>,
+    /// Split into numbered parts no taller than this many pixels
+    #[clap(long = "max-height")]
+    max_height: Option<u32>,
+    /// Split into numbered parts with at most this many images each
+    #[clap(long = "max-per-image")]
+    max_per_image: Option<usize>,
+}
+
+/// Upscaling of cards and crops
+#[derive(Args)]
+struct Scaling {
+    /// Scale cards up by a whole factor
+    #[clap(long, default_value = "1", parse(try_from_str = upscale::parse_factor))]
+    scale: u32,
+    /// How to scale, one of nearest or scale2x
+    #[clap(long, default_value = "nearest")]
+    scaler: Scaler,
+}
+
+impl Scaling {
+    fn upscale(&self) -> Upscale {
+        Upscale {
+            factor: self.scale,
+            scaler: self.scaler,
+        }
+    }
+}
+
+impl Layout {
+    fn options(&self) -> StitchOptions {
+        StitchOptions {
+            gutter: self.gutter,
+            margin: self.margin,
+            border_width: self.border_width,
+            border_color: self.border_color,
+            background: self.background,
+            alignment: self.align,
+            order: if self.column_major {
+                Order::ColumnMajor
+            } else {
+                Order::RowMajor
+            },
+        }
+    }
+
+    fn limits(&self) -> Limits {
+        Limits {
+            max_width: self.max_width,
+            max_height: self.max_height,
+            max_cards: self.max_per_image,
+        }
+    }
+}
+
+#[derive(Subcommand)]
+enum CacheCommands {
+    /// Remove cached screenshots that are not in any of the given directories
+    Prune {
+        #[clap(parse(from_os_str))]
+        sources: Vec<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ShareCommands {
+    /// Print the share code of a transcription or screenshot directory
+    Encode {
+        #[clap(required = true, parse(from_os_str))]
+        source: PathBuf,
+    },
+    /// Write the transcription in a share code
+    Decode {
+        #[clap(required = true)]
+        code: String,
+        #[clap(required = true, parse(from_os_str))]
+        output: PathBuf,
     },
 }
 
+fn read_pages(source: &Path, cache: &Cache) -> Result<Vec<Image>, ImageError> {
+    Ok(cache::cropped_images(source, cache)?
+        .into_iter()
+        .map(|screenshot| screenshot.image)
+        .collect())
+}
+
+// a transcription along with whatever else its source can tell
+struct Book {
+    transcription: Transcription,
+    cards: HashMap<usize, Image>,
+    file_date: Option<String>,
+}
+
+// read either a transcription file or a directory of screenshots, along with
+// the card images and the file date of the screenshots when they are available
+fn read_book(path: &Path, cache: &Cache) -> Result<Book, Box<dyn std::error::Error>> {
+    if !path.is_dir() {
+        return Ok(Book {
+            transcription: Transcription::read(path)?,
+            cards: HashMap::new(),
+            file_date: None,
+        });
+    }
+    let screenshots = cache::cropped_images(path, cache)?;
+    let pages = cache::transcribe_pages(&screenshots, &Calibration::default(), cache)?;
+    let cards = pages
+        .iter()
+        .flat_map(|page| page.cards.iter())
+        .map(|(entry, card)| (entry.uid, card.clone()))
+        .collect();
+    Ok(Book {
+        transcription: transcribe::into_transcription(&pages),
+        cards,
+        file_date: file_date(&screenshots)?,
+    })
+}
+
+fn save_parts(
+    output: &Path,
+    parts: &[Image],
+    limits: &Limits,
+    metadata: Option<&Metadata>,
+) -> Result<(), ImageError> {
+    for (i, part) in parts.iter().enumerate() {
+        let path = utils::part_path(output, i, parts.len());
+        if !limits.fits(part, 0) {
+            println!(
+                "warning: {} is {}x{}, a row or heading on its own is larger than the limits",
+                path.display(),
+                part.width(),
+                part.height()
+            );
+        }
+        match metadata {
+            Some(metadata) => metadata::imsave(&path, part, metadata)?,
+            None => crop::imsave(&path, part)?,
+        }
+    }
+    if parts.len() > 1 {
+        println!("wrote {} parts", parts.len());
+    }
+    Ok(())
+}
+
+fn file_date(screenshots: &[Screenshot]) -> std::io::Result<Option<String>> {
+    metadata::file_date(
+        screenshots
+            .iter()
+            .map(|screenshot| screenshot.path.as_path()),
+    )
+}
+
+fn read_calibration(path: &Option<PathBuf>) -> std::io::Result<Calibration> {
+    match path {
+        Some(path) => Calibration::read(path),
+        None => Ok(Calibration::default()),
+    }
+}
+
+fn print_histogram(title: &str, histogram: &Histogram, cuts: &[u32]) {
+    println!("{}", title);
+    println!("{}", histogram.format_ascii(cuts, 50));
+    println!();
+}
+
+// identify each page before stitching, otherwise a missing or duplicate page
+// shifts every following page onto the wrong tab
+fn arrange_pages(
+    images: &[Image],
+    skip_duplicates: bool,
+    fill_missing: bool,
+    calibration: &Calibration,
+) -> Result<Vec<Image>, Box<dyn std::error::Error>> {
+    let check = preflight::check_pages(images, calibration)?;
+    if !check.is_ok() {
+        eprint!("{}", check);
+    }
+    if !check.duplicates.is_empty() && !skip_duplicates {
+        return Err(
+            "found duplicate pages, use --skip-duplicates to keep the first of each".into(),
+        );
+    }
+    if !check.missing.is_empty() && !fill_missing {
+        return Err("found missing pages, use --fill-missing to replace them with blanks".into());
+    }
+    Ok(preflight::arrange_pages(images, &check)?)
+}
+
+fn print_changes(title: &str, changes: &[CardChange]) {
+    println!("{} ({})", title, changes.len());
+    for change in changes {
+        println!(
+            "  {:3} {} {} -> {}",
+            change.uid, change.name, change.before, change.after
+        );
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Cli::parse();
+    let cache = match cache::default_dir() {
+        Some(dir) if !args.no_cache => Cache::new(&dir),
+        _ => Cache::disabled(),
+    };
     match &args.command {
-        Commands::Crop { source, output } => {
+        Commands::Crop {
+            source,
+            output,
+            scaling,
+        } => {
             // it's totally possible that the image is poorly formatted, so we
             // guess the type
             let mut img = crop::imread(source)?;
             let (x, y) = crop::match_reference_page(&img)?;
             let cropped = crop::crop(&mut img, x, y)?;
-            crop::imsave(output, &cropped)?;
+            crop::imsave(output, &upscale::upscale(&cropped, &scaling.upscale()))?;
         }
-        Commands::CropCards { source, output } => {
+        Commands::CropCards {
+            source,
+            output,
+            scaling,
+        } => {
             // it's totally possible that the image is poorly formatted, so we
             // guess the type
             let mut img = crop::imread(source)?;
@@ -81,12 +582,26 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             for (i, card) in cards.iter().enumerate() {
                 let mut card_file = output.clone();
                 card_file.push(format!("{:02}.png", i));
-                crop::imsave(&card_file, card)?;
+                crop::imsave(&card_file, &upscale::upscale(card, &scaling.upscale()))?;
+            }
+        }
+        Commands::ClassifyCards { source, thresholds } => {
+            let mut img = crop::imread(source)?;
+            let (x, y) = crop::match_reference_page(&img)?;
+            let cropped = crop::crop(&mut img, x, y)?;
+            let calibration = read_calibration(thresholds)?;
+            let cards = crop::crop_cards(&calibration.compared(&cropped))?;
+            for (i, card) in cards.iter().enumerate() {
+                let classification = calibration.cards.classify(card);
+                println!(
+                    "{:02} {:?} {}",
+                    i, classification.state, classification.distance
+                );
             }
         }
         Commands::ReferenceBook { source, output } => {
             fs::create_dir_all(output)?;
-            let images = utils::get_cropped_images(source)?;
+            let images = read_pages(source, &cache)?;
             let names = utils::page_metadata().into_iter().map(|metadata| {
                 let mut output = output.clone();
                 output.push(format!(
@@ -99,22 +614,502 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 crop::imsave(&name, &img)?;
             }
         }
-        Commands::StitchPages { source, output } => {
-            let images = utils::get_cropped_images(source)?;
-            let stitched = stitch::stitch_images(images, 6);
-            crop::imsave(&output, &stitched)?;
+        Commands::StitchPages {
+            source,
+            output,
+            skip_duplicates,
+            fill_missing,
+            layout,
+        } => {
+            let images = read_pages(source, &cache)?;
+            let images = arrange_pages(
+                &images,
+                *skip_duplicates,
+                *fill_missing,
+                &Calibration::default(),
+            )?;
+            let options = layout.options();
+            let sections = vec![(String::new(), images)];
+            let parts = utils::split_sections(sections, 6, &layout.limits(), |sections, width| {
+                utils::stitch_sections(sections, width, &options, false)
+            });
+            save_parts(output, &parts, &layout.limits(), None)?;
         }
         Commands::StitchCards {
             source,
             output,
             generate_stats,
+            skip_duplicates,
+            fill_missing,
+            thresholds,
+            theme,
+            palette,
+            layout,
+            scaling,
+            title,
+            group_by,
+            sort_by,
+            headings,
+            captions,
+            badges,
+            incomplete,
+            heatmap,
+            counts,
+            embed,
         } => {
-            let mut images = utils::get_cropped_images(source)?;
+            let screenshots = cache::cropped_images(source, &cache)?;
+            let mut images: Vec<Image> = screenshots
+                .iter()
+                .map(|screenshot| screenshot.image.clone())
+                .collect();
             if *generate_stats {
                 return Ok(println!("{:?}", utils::get_empty_card_mse(&mut images)));
             }
-            let stitched = utils::stitch_cards(&mut images, 4 * 6);
-            crop::imsave(&output, &stitched)?;
+            let palette = match palette {
+                Some(path) => Palette::read(path)?,
+                None => theme.clone(),
+            };
+            let calibration = read_calibration(thresholds)?;
+            let mut labels = Labels {
+                header: Vec::new(),
+                headings: *headings,
+                caption: *captions,
+            };
+            let arrangement = Arrangement {
+                group_by: match group_by {
+                    GroupBy::None if *headings => GroupBy::Tab,
+                    _ => *group_by,
+                },
+                sort_by: *sort_by,
+            };
+            let needs_counts = title.is_some()
+                || *embed
+                || *badges
+                || *heatmap
+                || *incomplete != Incomplete::Keep
+                || *sort_by == SortBy::Count;
+            let transcription = match counts {
+                Some(path) => Some(Transcription::read(path)?),
+                None if needs_counts => {
+                    let pages = cache::transcribe_pages(&screenshots, &calibration, &cache)?;
+                    Some(transcribe::into_transcription(&pages))
+                }
+                None => None,
+            };
+            if let (Some(title), Some(transcription)) = (title, &transcription) {
+                let stats = stats::stats(transcription);
+                labels.header = vec![
+                    title.clone(),
+                    labels::today(),
+                    format!("{:.1}% complete", stats.overall.percent),
+                ];
+            }
+            let metadata = match &transcription {
+                Some(transcription) if *embed => Some(Metadata::new(
+                    title.clone(),
+                    file_date(&screenshots)?,
+                    Some(transcription.clone()),
+                )),
+                _ => None,
+            };
+            let overlay = Overlay {
+                counts: transcription.map(|transcription| transcription.counts()),
+                badges: *badges,
+                incomplete: *incomplete,
+                heatmap: *heatmap,
+            };
+            let images = arrange_pages(&images, *skip_duplicates, *fill_missing, &calibration)?;
+            let parts = utils::stitch_cards(
+                &images,
+                4 * 6,
+                &calibration,
+                &layout.options(),
+                &layout.limits(),
+                &CardStyle {
+                    palette,
+                    labels,
+                    overlay,
+                    arrangement,
+                    upscale: scaling.upscale(),
+                },
+            );
+            save_parts(output, &parts, &layout.limits(), metadata.as_ref())?;
+        }
+        Commands::ExtractSprites {
+            source,
+            output,
+            thresholds,
+        } => {
+            let calibration = read_calibration(thresholds)?;
+            let screenshots = cache::cropped_images(source, &cache)?;
+            let pages = cache::transcribe_pages(&screenshots, &calibration, &cache)?;
+            let palette = Palette::in_game();
+            fs::create_dir_all(output)?;
+            for (uid, name, sprite) in sprites::extract_sprites(&pages, &palette)? {
+                crop::imsave(&output.join(sprites::sprite_name(uid, &name)), &sprite)?;
+            }
+        }
+        Commands::Atlas {
+            source,
+            output,
+            cards,
+            thresholds,
+        } => {
+            // the frame map refers to the texture by its file name
+            let image = match output.file_name() {
+                Some(name) => name.to_string_lossy(),
+                None => return Err(format!("{} is not a file", output.display()).into()),
+            };
+            let calibration = read_calibration(thresholds)?;
+            let screenshots = cache::cropped_images(source, &cache)?;
+            let pages = cache::transcribe_pages(&screenshots, &calibration, &cache)?;
+            let entries = if *cards {
+                pages
+                    .iter()
+                    .flat_map(|page| page.cards.iter())
+                    .filter(|(entry, _)| entry.count > 0)
+                    .map(|(entry, card)| (entry.uid, entry.name.clone(), card.clone()))
+                    .collect()
+            } else {
+                sprites::extract_sprites(&pages, &Palette::in_game())?
+            };
+            if entries.is_empty() {
+                return Err("found no cards to pack into an atlas".into());
+            }
+            let (texture, frames) = atlas::build(entries, !*cards, &image);
+            crop::imsave(output, &texture)?;
+            frames.write(&output.with_extension("json"))?;
+        }
+        Commands::Calibrate {
+            source,
+            output,
+            plots,
+            bins,
+            page_metric,
+            card_metric,
+            tag_metric,
+            labels,
+            denoise,
+        } => {
+            let mut images = read_pages(source, &cache)?;
+            if *denoise {
+                images = images.iter().map(quality::denoise).collect();
+            }
+            let labels = match labels {
+                Some(path) => Some(calibrate::labels(
+                    &images,
+                    &Transcription::read(path)?,
+                    *page_metric,
+                )?),
+                None => None,
+            };
+            let distributions =
+                calibrate::distributions(&images, *card_metric, *tag_metric, labels)?;
+            let mut calibration = calibrate::suggest(&distributions, *page_metric);
+            calibration.denoise = *denoise;
+            let card_cuts = [calibration.cards.empty, calibration.cards.unseen];
+            let tag_cuts: Vec<u32> = calibration.tag.into_iter().collect();
+            let card_histogram = Histogram::new(&distributions.cards, *bins);
+            let tag_histogram = Histogram::new(&distributions.tags, *bins);
+            print_histogram(
+                "distance of cards from the empty card",
+                &card_histogram,
+                &card_cuts,
+            );
+            print_histogram(
+                "distance of seen tags from the seed tags",
+                &tag_histogram,
+                &tag_cuts,
+            );
+            println!(
+                "suggested thresholds: {}",
+                serde_json::to_string(&calibration)?
+            );
+            if let Some(plots) = plots {
+                fs::create_dir_all(plots)?;
+                crop::imsave(&plots.join("cards.png"), &card_histogram.render(&card_cuts))?;
+                crop::imsave(&plots.join("tags.png"), &tag_histogram.render(&tag_cuts))?;
+            }
+            if let Some(output) = output {
+                calibration.write(output)?;
+            }
+        }
+        Commands::Quality { source } => {
+            let mut paths: Vec<PathBuf> = fs::read_dir(source)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<_, _>>()?;
+            paths.sort();
+            for path in paths {
+                let report = quality::assess(&path)?;
+                let jpeg_quality = match report.jpeg_quality {
+                    Some(quality) => quality.to_string(),
+                    None => "-".into(),
+                };
+                println!(
+                    "{:<40} quality {:>3} blockiness {:.2}{}",
+                    path.display(),
+                    jpeg_quality,
+                    report.blockiness,
+                    if report.is_degraded() {
+                        " degraded"
+                    } else {
+                        ""
+                    }
+                );
+            }
+        }
+        Commands::Plan { transcription } => {
+            let transcription = Transcription::read(transcription)?;
+            for (i, stop) in plan::plan(&transcription).iter().enumerate() {
+                println!(
+                    "{:3}. {} - {} ({} cards remaining)",
+                    i + 1,
+                    stop.town,
+                    stop.map,
+                    stop.remaining()
+                );
+                for target in &stop.targets {
+                    println!("       {} {}/{}", target.name, target.count, MAX_COUNT);
+                }
+            }
+        }
+        Commands::Stats {
+            transcription,
+            json,
+        } => {
+            let stats = stats::stats(&Transcription::read(transcription)?);
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&stats)?);
+            } else {
+                println!("{}", stats::format_table(&stats));
+            }
+        }
+        Commands::Missing {
+            source,
+            output,
+            columns,
+            layout,
+            scaling,
+            title,
+            group_by,
+            sort_by,
+            headings,
+            embed,
+        } => {
+            let book = read_book(source, &cache)?;
+            let cards = missing::missing_cards(&book.transcription, &book.cards)?;
+            if cards.is_empty() {
+                println!("every card is complete");
+                return Ok(());
+            }
+            let arrangement = Arrangement {
+                group_by: *group_by,
+                sort_by: *sort_by,
+            };
+            let count = cards.len();
+            let mut labels = Labels {
+                headings: *headings,
+                ..Labels::default()
+            };
+            if let Some(title) = title {
+                labels.header = vec![
+                    title.clone(),
+                    labels::today(),
+                    format!("{} cards missing", count),
+                ];
+            }
+            let parts = missing::poster(
+                cards,
+                *columns,
+                &layout.options(),
+                &layout.limits(),
+                &arrangement,
+                &labels,
+                &scaling.upscale(),
+            );
+            let metadata = if *embed {
+                Some(Metadata::new(
+                    title.clone(),
+                    book.file_date,
+                    Some(book.transcription),
+                ))
+            } else {
+                None
+            };
+            save_parts(output, &parts, &layout.limits(), metadata.as_ref())?;
+            println!("{} cards missing", count);
+        }
+        Commands::Inspect {
+            image,
+            transcription,
+            json,
+        } => {
+            let metadata = metadata::read(image)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&metadata)?);
+            } else {
+                let fields = [
+                    ("software", &metadata.software),
+                    ("character", &metadata.character),
+                    ("screenshot file date", &metadata.file_date),
+                ];
+                for (name, value) in fields {
+                    println!("{}: {}", name, value.as_deref().unwrap_or("-"));
+                }
+                if let Some(embedded) = &metadata.transcription {
+                    println!();
+                    println!("{}", stats::format_table(&stats::stats(embedded)));
+                }
+            }
+            match (transcription, metadata.transcription) {
+                (Some(path), Some(embedded)) => embedded.write(path)?,
+                (Some(_), None) => println!("warning: no transcription is embedded"),
+                _ => (),
+            }
+        }
+        Commands::Transcribe {
+            source,
+            output,
+            thresholds,
+            denoise,
+            cross_check,
+            reference,
+        } => {
+            let mut calibration = read_calibration(thresholds)?;
+            calibration.denoise |= *denoise;
+            let screenshots = cache::cropped_images(source, &cache)?;
+            for screenshot in &screenshots {
+                let report = quality::assess(&screenshot.path)?;
+                if report.is_degraded() {
+                    println!(
+                        "warning: {} is heavily compressed, counts may be misread",
+                        screenshot.path.display()
+                    );
+                }
+            }
+            let pages = cache::transcribe_pages(&screenshots, &calibration, &cache)?;
+            for page in &pages {
+                for (slot, kind) in &page.occluded {
+                    println!(
+                        "warning: card {} on page {} is covered by the {}, take another screenshot",
+                        slot, page.page_id, kind
+                    );
+                }
+            }
+            for uid in pages.iter().flat_map(|page| page.uncertain.iter()) {
+                println!("warning: the count of card {} may be misread", uid);
+            }
+            if *cross_check || reference.is_some() {
+                // ncc ignores the difference in brightness between faded and seen artwork
+                let mut index = CardIndex::reference_book(MetricKind::Ncc)?;
+                if let Some(reference) = reference {
+                    let checked = cache::transcribe_pages(
+                        &cache::cropped_images(reference, &cache)?,
+                        &calibration,
+                        &cache,
+                    )?;
+                    index.insert_pages(&checked);
+                }
+                for mismatch in recognize::cross_check(&pages, &index) {
+                    println!(
+                        "warning: card {} ({}) looks like card {} ({}), check the page order",
+                        mismatch.uid,
+                        mismatch.name,
+                        mismatch.recognized.uid,
+                        mismatch.recognized_name
+                    );
+                }
+            }
+            transcribe::into_transcription(&pages).write(output)?;
+        }
+        Commands::Cache {
+            command: CacheCommands::Prune { sources },
+        } => {
+            let mut keep = HashSet::new();
+            for source in sources {
+                keep.extend(cache::keys(source)?);
+            }
+            println!("removed {} cached screenshots", cache.prune(&keep)?);
+        }
+        Commands::Share {
+            command: ShareCommands::Encode { source },
+        } => {
+            let transcription = read_book(source, &cache)?.transcription;
+            println!("{}", share::encode(&transcription)?);
+        }
+        Commands::Share {
+            command: ShareCommands::Decode { code, output },
+        } => {
+            let transcription = share::decode(code)?;
+            transcription.write(output)?;
+            println!("{}", stats::format_table(&stats::stats(&transcription)));
+        }
+        Commands::Diff {
+            before,
+            after,
+            image,
+        } => {
+            let before = read_book(before, &cache)?.transcription;
+            let after = read_book(after, &cache)?;
+            let diff = diff::diff(&before, &after.transcription);
+            print_changes("newly seen", &diff.newly_seen);
+            print_changes("increased", &diff.increased);
+            print_changes("completed", &diff.completed);
+            print_changes("decreased, likely misread", &diff.decreased);
+            if let Some(image) = image {
+                let changed: Vec<Image> = diff
+                    .changed()
+                    .iter()
+                    .filter_map(|change| after.cards.get(&change.uid).cloned())
+                    .collect();
+                if changed.is_empty() {
+                    println!("no card images to stitch");
+                } else {
+                    crop::imsave(
+                        image,
+                        &stitch::stitch_images(changed, 10, &StitchOptions::default()),
+                    )?;
+                }
+            }
+        }
+        Commands::Merge {
+            output,
+            sources,
+            image,
+        } => {
+            let mut captures = Vec::new();
+            for source in sources {
+                captures.extend(merge::read_captures(source)?);
+            }
+            let pages = merge::merge(captures);
+            for page_id in merge::missing_pages(&pages) {
+                println!("warning: page {} is missing", page_id);
+            }
+            merge::into_transcription(&pages).write(output)?;
+            if let Some(image) = image {
+                // blank pages keep the cards after them on the right tab
+                let blank = merge::pages_without_images(&pages);
+                for page_id in &blank {
+                    println!(
+                        "warning: page {} only comes from a transcription, it is blank in the image",
+                        page_id
+                    );
+                }
+                if blank.len() == pages.len() {
+                    println!("no screenshots to stitch");
+                    return Ok(());
+                }
+                let images = merge::page_images(&pages)?;
+                let parts = utils::stitch_cards(
+                    &images,
+                    4 * 6,
+                    &Calibration::default(),
+                    &StitchOptions::default(),
+                    &Limits::default(),
+                    &CardStyle::default(),
+                );
+                save_parts(image, &parts, &Limits::default(), None)?;
+            }
         }
     }
     Ok(())
@@ -1,12 +1,21 @@
+use super::metrics::{Metric, MetricKind, Mse};
+use super::occlusion::{self, Mask};
 use image::{imageops, io, ImageBuffer, ImageError, Rgba, RgbaImage};
 use ndarray::{stack, Array2, ArrayBase, Axis, ViewRepr};
 use nshare::ToNdarray2;
 use rustfft::{num_complex::Complex, FftDirection, FftPlanner};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
 use std::io::Cursor;
 use std::path::Path;
 
 pub type Image = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
+/// The artwork inside the frame of a card, as x, y, width and height.
+pub const ARTWORK: (u32, u32, u32, u32) = (3, 4, 27, 38);
+/// The count tag in the lower left of a card, as x, y, width and height.
+pub const TAG: (u32, u32, u32, u32) = (5, 31, 6, 9);
+
 fn path_as_string(path: &Path) -> String {
     path.to_path_buf().into_os_string().into_string().unwrap()
 }
@@ -22,23 +31,45 @@ pub fn imsave(output: &Path, img: &Image) -> Result<(), ImageError> {
     img.save(&path_as_string(output))
 }
 
-fn get_empty_card() -> Result<Image, ImageError> {
-    let reference_bytes = include_bytes!("assets/empty_card.png");
-    Ok(io::Reader::new(Cursor::new(reference_bytes))
+pub fn imdecode(bytes: &[u8]) -> Result<Image, ImageError> {
+    Ok(io::Reader::new(Cursor::new(bytes))
         .with_guessed_format()?
         .decode()?
         .into_rgba8())
 }
 
+pub fn get_empty_card() -> Result<Image, ImageError> {
+    imdecode(include_bytes!("assets/empty_card.png"))
+}
+
+/// The first card of the windows reference page, which is selected and has a
+/// highlight ring around its artwork.
+pub fn get_selected_card() -> Result<Image, ImageError> {
+    Ok(crop_cards(&get_reference_page()?)?.remove(0))
+}
+
+/// A page where every slot is an empty card, used in place of a missing page.
+pub fn blank_page() -> Result<Image, ImageError> {
+    let empty_card = get_empty_card()?;
+    let mut page = RgbaImage::new(empty_card.width() * 5, empty_card.height() * 5);
+    for i in 0..5 {
+        for j in 0..5 {
+            imageops::overlay(
+                &mut page,
+                &empty_card,
+                j * empty_card.width(),
+                i * empty_card.height(),
+            );
+        }
+    }
+    Ok(page)
+}
+
 fn get_reference_page() -> Result<Image, ImageError> {
-    let reference_bytes = include_bytes!("assets/reference_page_win.png");
-    Ok(io::Reader::new(Cursor::new(reference_bytes))
-        .with_guessed_format()?
-        .decode()?
-        .into_rgba8())
+    imdecode(include_bytes!("assets/reference_page_win.png"))
 }
 
-fn into_grayscale_array(img: &Image) -> Array2<u8> {
+pub(crate) fn into_grayscale_array(img: &Image) -> Array2<u8> {
     imageops::colorops::grayscale(img).into_ndarray2()
 }
 
@@ -146,24 +177,188 @@ pub fn crop_cards(img: &Image) -> Result<Vec<Image>, ImageError> {
     Ok(cards)
 }
 
+/// Shorthand for `metrics::Mse`.
 pub fn mse(img: &Image, reference: &Image) -> u32 {
-    let gray_img = into_grayscale_array(img);
-    let gray_ref = into_grayscale_array(reference);
-
-    // calculatinng mse
-    let acc: i32 = gray_img
-        .iter()
-        .zip(gray_ref.iter())
-        .map(|(x, y)| (*x as i32 - *y as i32).pow(2))
-        .sum();
-    let denom = img.width() * img.height();
-    return (acc / denom as i32) as u32;
+    Mse.distance(img, reference)
 }
 
 // good default threshold is 100
 pub fn card_mse(img: &Image) -> u32 {
+    card_distance(img, MetricKind::Mse)
+}
+
+pub fn card_distance(img: &Image, metric: MetricKind) -> u32 {
     let empty_card = get_empty_card().unwrap();
-    mse(img, &empty_card)
+    metric.distance(img, &empty_card)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CardState {
+    /// A slot past the last card on the page
+    Empty,
+    /// The silhouette of a card that has not been collected
+    Unseen,
+    /// A card with at least one copy
+    Seen,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Classification {
+    pub state: CardState,
+    /// The mse against the empty card
+    pub distance: u32,
+}
+
+// the cut between two labelled classes that misclassifies the fewest samples,
+// moved halfway across the gap to the next distance
+fn labelled_cut(lower: &[u32], upper: &[u32]) -> u32 {
+    let mut cuts: Vec<u32> = lower.iter().chain(upper).copied().collect();
+    cuts.sort_unstable();
+    cuts.dedup();
+    let errors = |cut: u32| {
+        lower.iter().filter(|x| **x > cut).count() + upper.iter().filter(|x| **x <= cut).count()
+    };
+    let best = cuts
+        .iter()
+        .copied()
+        .min_by_key(|cut| errors(*cut))
+        .unwrap_or(0);
+    match cuts.iter().find(|x| **x > best) {
+        Some(next) => best + (next - best) / 2,
+        None => best,
+    }
+}
+
+/// Otsu's method with two thresholds, splitting the distances into three
+/// classes by maximizing the between-class variance.
+pub fn otsu_thresholds(values: &[u32]) -> (u32, u32) {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let n = sorted.len();
+    // prefix sums, so the mean of any class is cheap to compute
+    let mut prefix = vec![0.0];
+    for x in &sorted {
+        prefix.push(prefix.last().unwrap() + *x as f64);
+    }
+    let total_mean = prefix[n] / n as f64;
+    let class_term = |start: usize, end: usize| {
+        let count = (end - start) as f64;
+        let mean = (prefix[end] - prefix[start]) / count;
+        count / n as f64 * (mean - total_mean).powi(2)
+    };
+    let (mut best, mut best_variance) = ((0, 0), -1.0);
+    for i in 1..n {
+        if sorted[i - 1] == sorted[i] {
+            continue;
+        }
+        for j in i + 1..n {
+            if sorted[j - 1] == sorted[j] {
+                continue;
+            }
+            let variance = class_term(0, i) + class_term(i, j) + class_term(j, n);
+            if variance > best_variance {
+                best_variance = variance;
+                best = (sorted[i - 1], sorted[j - 1]);
+            }
+        }
+    }
+    best
+}
+
+/// Classify cards by their distance from the empty card.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct CardClassifier {
+    /// Cards at or below this distance are empty slots
+    pub empty: u32,
+    /// Cards at or below this distance are unseen silhouettes
+    pub unseen: u32,
+    /// The metric used to compare against the empty card
+    #[serde(default)]
+    pub metric: MetricKind,
+}
+
+impl Default for CardClassifier {
+    // the thresholds from python, `calibrate --labels` fits others to a
+    // transcribed book
+    fn default() -> Self {
+        serde_json::from_str(include_str!("assets/thresholds.json")).unwrap()
+    }
+}
+
+impl CardClassifier {
+    pub fn read(path: &Path) -> std::io::Result<Self> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+
+    /// Learn the thresholds from distances labelled with their state, cutting
+    /// between each pair of neighboring states where the fewest samples end up
+    /// on the wrong side. Pairs missing a state keep the default threshold.
+    pub fn fit(samples: &[(u32, CardState)], metric: MetricKind) -> Self {
+        let select = |state: CardState| -> Vec<u32> {
+            samples
+                .iter()
+                .filter(|(_, s)| *s == state)
+                .map(|(distance, _)| *distance)
+                .collect()
+        };
+        let default = CardClassifier::default();
+        let fit_pair = |lower, upper, fallback| {
+            let (lower, upper) = (select(lower), select(upper));
+            if lower.is_empty() || upper.is_empty() {
+                fallback
+            } else {
+                labelled_cut(&lower, &upper)
+            }
+        };
+        CardClassifier {
+            empty: fit_pair(CardState::Empty, CardState::Unseen, default.empty),
+            unseen: fit_pair(CardState::Unseen, CardState::Seen, default.unseen),
+            metric,
+        }
+    }
+
+    /// Learn the thresholds from unlabelled distances, assuming that all three
+    /// states are present.
+    pub fn from_distances(distances: &[u32], metric: MetricKind) -> Self {
+        let (empty, unseen) = otsu_thresholds(distances);
+        CardClassifier {
+            empty,
+            unseen,
+            metric,
+        }
+    }
+
+    pub fn classify_distance(&self, distance: u32) -> CardState {
+        if distance <= self.empty {
+            CardState::Empty
+        } else if distance <= self.unseen {
+            CardState::Unseen
+        } else {
+            CardState::Seen
+        }
+    }
+
+    pub fn classify(&self, card: &Image) -> Classification {
+        let distance = card_distance(card, self.metric);
+        Classification {
+            state: self.classify_distance(distance),
+            distance,
+        }
+    }
+
+    /// Classify while ignoring the pixels under the mask.
+    pub fn classify_masked(&self, card: &Image, mask: &Mask) -> Classification {
+        let empty_card = get_empty_card().unwrap();
+        let distance = occlusion::masked_distance(self.metric, card, &empty_card, mask);
+        Classification {
+            state: self.classify_distance(distance),
+            distance,
+        }
+    }
 }
 
 // remove the background from a card
@@ -171,8 +366,45 @@ pub fn replace_background(img: &mut Image, color: Rgba<u8>) {
     // replace the background with our own custom color
     let mut background = RgbaImage::from_fn(img.width(), img.height(), |_, _| color);
     // see notebook, but we go from [4:-3, 3:-3] in numpy
+    let (x, y, w, h) = ARTWORK;
     let mut cloned = img.clone();
-    let cropped = imageops::crop(&mut cloned, 3, 4, 27, 38);
-    imageops::overlay(&mut background, &cropped, 3, 4);
+    let cropped = imageops::crop(&mut cloned, x, y, w, h);
+    imageops::overlay(&mut background, &cropped, x, y);
     *img = background;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn otsu_thresholds_split_three_clusters() {
+        let values = [100, 110, 120, 1000, 1100, 1200, 9000, 9500, 10000];
+        assert_eq!(otsu_thresholds(&values), (120, 1200));
+    }
+
+    #[test]
+    fn fit_cuts_between_labelled_states() {
+        let samples = [
+            (100, CardState::Empty),
+            (160, CardState::Empty),
+            (900, CardState::Unseen),
+            (2000, CardState::Unseen),
+            // an outlier should not move the cut away from the gap
+            (20000, CardState::Unseen),
+            (9000, CardState::Seen),
+            (12000, CardState::Seen),
+        ];
+        let classifier = CardClassifier::fit(&samples, MetricKind::Mse);
+        assert_eq!(classifier.empty, 530);
+        assert_eq!(classifier.unseen, 5500);
+    }
+
+    #[test]
+    fn fit_keeps_defaults_for_missing_states() {
+        let samples = [(100, CardState::Empty), (900, CardState::Unseen)];
+        let classifier = CardClassifier::fit(&samples, MetricKind::Mse);
+        assert_eq!(classifier.empty, 500);
+        assert_eq!(classifier.unseen, CardClassifier::default().unseen);
+    }
+}
@@ -0,0 +1,77 @@
+use super::transcription::{Transcription, MAX_COUNT};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug)]
+pub struct CardChange {
+    pub uid: usize,
+    pub name: String,
+    pub before: u8,
+    pub after: u8,
+}
+
+/// Progress between two transcriptions of the same character.
+#[derive(Clone, Debug, Default)]
+pub struct Diff {
+    /// Cards that went from no copies to at least one
+    pub newly_seen: Vec<CardChange>,
+    /// Cards that already had a copy and gained more
+    pub increased: Vec<CardChange>,
+    /// Cards that reached the maximum count
+    pub completed: Vec<CardChange>,
+    /// Counts can never go down, so these are likely misreads
+    pub decreased: Vec<CardChange>,
+}
+
+impl Diff {
+    /// Every card whose count changed, ordered by uid.
+    pub fn changed(&self) -> Vec<&CardChange> {
+        let mut changed: Vec<&CardChange> = self
+            .newly_seen
+            .iter()
+            .chain(self.increased.iter())
+            .chain(self.decreased.iter())
+            .collect();
+        changed.sort_by_key(|change| change.uid);
+        changed
+    }
+}
+
+pub fn diff(before: &Transcription, after: &Transcription) -> Diff {
+    // use an ordered map so changes are listed in book order
+    let mut cards = BTreeMap::new();
+    for entry in &before.data {
+        cards.insert(entry.uid, (entry.name.clone(), entry.count, None));
+    }
+    for entry in &after.data {
+        cards
+            .entry(entry.uid)
+            .or_insert_with(|| (entry.name.clone(), 0, None))
+            .2 = Some(entry.count);
+    }
+
+    let mut diff = Diff::default();
+    for (uid, (name, before, after)) in cards {
+        // the page was not captured the second time around
+        let after = match after {
+            Some(after) => after,
+            None => continue,
+        };
+        let change = CardChange {
+            uid,
+            name,
+            before,
+            after,
+        };
+        if after >= MAX_COUNT && before < MAX_COUNT {
+            diff.completed.push(change.clone());
+        }
+        if after < before {
+            diff.decreased.push(change);
+        } else if after > before && before == 0 {
+            diff.newly_seen.push(change);
+        } else if after > before {
+            diff.increased.push(change);
+        }
+    }
+    diff
+}
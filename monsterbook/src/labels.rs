@@ -0,0 +1,168 @@
+use super::crop::Image;
+use super::font;
+use image::{imageops, Rgba, RgbaImage};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// space around lines of text
+const PADDING: u32 = 4;
+// header lines are drawn at twice the size of headings and captions
+const HEADER_SCALE: u32 = 2;
+const TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+// drawn one pixel down and to the right, so the text reads on any background
+const SHADOW: Rgba<u8> = Rgba([0, 0, 0, 255]);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Caption {
+    Name,
+    Uid,
+}
+
+impl FromStr for Caption {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "name" => Ok(Caption::Name),
+            "uid" => Ok(Caption::Uid),
+            _ => Err(format!("unknown caption {}, expected one of name, uid", s)),
+        }
+    }
+}
+
+/// Text drawn onto stitched images. Nothing is drawn by default.
+#[derive(Clone, Debug, Default)]
+pub struct Labels {
+    /// Lines above the image, such as the character name and date
+    pub header: Vec<String>,
+    /// Start each section of cards with its name
+    pub headings: bool,
+    /// Text below each card
+    pub caption: Option<Caption>,
+}
+
+fn draw_shadowed(img: &mut Image, x: u32, y: u32, text: &str, scale: u32) {
+    font::draw_text(img, x + 1, y + 1, text, SHADOW, scale);
+    font::draw_text(img, x, y, text, TEXT, scale);
+}
+
+fn with_lines(img: &Image, lines: &[String], scale: u32, background: Rgba<u8>) -> Image {
+    if lines.is_empty() {
+        return img.clone();
+    }
+    let line_height = font::text_height(scale) + PADDING;
+    let band = PADDING + lines.len() as u32 * line_height;
+    let widest = lines
+        .iter()
+        .map(|line| font::text_width(line, scale))
+        .max()
+        .unwrap();
+    let width = img.width().max(widest + 2 * PADDING);
+    let mut canvas = RgbaImage::from_pixel(width, band + img.height(), background);
+    for (i, line) in lines.iter().enumerate() {
+        let y = PADDING + i as u32 * line_height;
+        draw_shadowed(&mut canvas, PADDING, y, line, scale);
+    }
+    imageops::overlay(&mut canvas, img, 0, band);
+    canvas
+}
+
+/// Add a band of large text above an image.
+pub fn with_header(img: &Image, lines: &[String], background: Rgba<u8>) -> Image {
+    with_lines(img, lines, HEADER_SCALE, background)
+}
+
+/// Add a single line of text above an image.
+pub fn with_heading(img: &Image, heading: &str, background: Rgba<u8>) -> Image {
+    with_lines(img, &[heading.to_string()], 1, background)
+}
+
+// captions break at the space that keeps the longer of two lines shortest,
+// and text without spaces stays on one line
+fn caption_lines(text: &str) -> Vec<String> {
+    let words: Vec<&str> = text.split(' ').collect();
+    (1..words.len())
+        .map(|i| vec![words[..i].join(" "), words[i..].join(" ")])
+        .min_by_key(|lines| lines.iter().map(|line| line.chars().count()).max())
+        .unwrap_or_else(|| vec![text.to_string()])
+}
+
+/// The width a caption needs to fit on its two lines, with room for the
+/// shadow.
+pub fn caption_width(text: &str) -> u32 {
+    let widest = caption_lines(text)
+        .iter()
+        .map(|line| font::text_width(line, 1))
+        .max()
+        .unwrap_or(0);
+    widest + 2
+}
+
+/// Add text below a card, wrapped onto two lines. The card is centered in a
+/// strip at least `width` pixels wide, so that cards with captions of
+/// different lengths can share that width.
+pub fn with_caption(card: &Image, text: &str, width: u32, background: Rgba<u8>) -> Image {
+    let width = width.max(card.width());
+    let line_height = font::text_height(1) + 2;
+    let band = 2 * line_height + 1;
+    let mut canvas = RgbaImage::from_pixel(width, card.height() + band, background);
+    imageops::overlay(&mut canvas, card, (width - card.width()) / 2, 0);
+    for (i, line) in caption_lines(text).iter().enumerate() {
+        let line = font::truncate(line, width, 1);
+        let x = (width - font::text_width(&line, 1)) / 2;
+        let y = card.height() + 1 + i as u32 * line_height;
+        draw_shadowed(&mut canvas, x, y, &line, 1);
+    }
+    canvas
+}
+
+/// Add lines of text to the right of a card, cut short so the result is
+/// `width` pixels wide.
+pub fn beside(card: &Image, lines: &[String], width: u32, background: Rgba<u8>) -> Image {
+    let mut canvas = RgbaImage::from_pixel(width.max(card.width()), card.height(), background);
+    imageops::overlay(&mut canvas, card, 0, 0);
+    let x = card.width() + PADDING;
+    let room = canvas.width().saturating_sub(x + 1);
+    for (i, line) in lines.iter().enumerate() {
+        let y = 2 + i as u32 * (font::text_height(1) + PADDING);
+        draw_shadowed(&mut canvas, x, y, &font::truncate(line, room, 1), 1);
+    }
+    canvas
+}
+
+/// Stack images from top to bottom, aligned to the left.
+pub fn stack(images: &[Image], gap: u32, background: Rgba<u8>) -> Image {
+    let width = images.iter().map(|img| img.width()).max().unwrap_or(0);
+    let height = images.iter().map(|img| img.height() + gap).sum::<u32>();
+    let mut canvas = RgbaImage::from_pixel(width, height.saturating_sub(gap), background);
+    let mut y = 0;
+    for img in images {
+        imageops::overlay(&mut canvas, img, 0, y);
+        y += img.height() + gap;
+    }
+    canvas
+}
+
+/// Today's date in UTC, as YYYY-MM-DD.
+pub fn today() -> String {
+    date(SystemTime::now())
+}
+
+/// A date in UTC, as YYYY-MM-DD.
+pub fn date(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0);
+    // days to a civil date, from http://howardhinnant.github.io/date_algorithms.html
+    let z = (seconds / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -1,7 +1,33 @@
 extern crate image;
 extern crate nshare;
+extern crate serde;
 
 pub mod app;
+pub mod arrange;
+pub mod atlas;
+pub mod cache;
+pub mod calibrate;
+pub mod catalog;
 pub mod crop;
+pub mod diff;
+pub mod font;
+pub mod labels;
+pub mod merge;
+pub mod metadata;
+pub mod metrics;
+pub mod missing;
+pub mod occlusion;
+pub mod overlay;
+pub mod palette;
+pub mod plan;
+pub mod preflight;
+pub mod quality;
+pub mod recognize;
+pub mod share;
+pub mod sprites;
+pub mod stats;
 pub mod stitch;
+pub mod transcribe;
+pub mod transcription;
+pub mod upscale;
 pub mod utils;
@@ -0,0 +1,115 @@
+use super::calibrate::Calibration;
+use super::crop::{self, Image};
+use super::transcribe;
+use super::transcription::{Entry, Transcription};
+use super::utils;
+use image::ImageError;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// A single page from one capture session.
+pub struct PageCapture {
+    pub page_id: usize,
+    pub modified: SystemTime,
+    pub entries: Vec<Entry>,
+    pub image: Option<Image>,
+}
+
+fn modified(path: &Path) -> Result<SystemTime, ImageError> {
+    Ok(fs::metadata(path)?.modified()?)
+}
+
+/// Read the pages from either a transcription file or a directory of
+/// screenshots. Pages from a transcription share the file's timestamp.
+pub fn read_captures(source: &Path) -> Result<Vec<PageCapture>, ImageError> {
+    if !source.is_dir() {
+        let transcription = Transcription::read(source)?;
+        let modified = modified(source)?;
+        let mut pages: BTreeMap<usize, Vec<Entry>> = BTreeMap::new();
+        for entry in transcription.data {
+            if let Some(page_id) = utils::page_of(entry.uid) {
+                pages.entry(page_id).or_default().push(entry);
+            }
+        }
+        return Ok(pages
+            .into_iter()
+            .map(|(page_id, entries)| PageCapture {
+                page_id,
+                modified,
+                entries,
+                image: None,
+            })
+            .collect());
+    }
+
+    let (paths, images): (Vec<_>, Vec<_>) = utils::get_cropped_images_with_paths(source)?
+        .into_iter()
+        .unzip();
+    let pages = transcribe::transcribe_pages(&images, &Calibration::default())?;
+    let mut captures = Vec::new();
+    for ((path, image), page) in paths.iter().zip(images).zip(pages) {
+        captures.push(PageCapture {
+            page_id: page.page_id,
+            modified: modified(path)?,
+            entries: page.cards.into_iter().map(|(entry, _)| entry).collect(),
+            image: Some(image),
+        });
+    }
+    Ok(captures)
+}
+
+/// Keep the most recent capture of each page, ordered by page id.
+pub fn merge(captures: Vec<PageCapture>) -> Vec<PageCapture> {
+    let mut latest: BTreeMap<usize, PageCapture> = BTreeMap::new();
+    for capture in captures {
+        match latest.get(&capture.page_id) {
+            Some(existing) if existing.modified >= capture.modified => {}
+            _ => {
+                latest.insert(capture.page_id, capture);
+            }
+        }
+    }
+    latest.into_values().collect()
+}
+
+/// Page ids from the book that have no capture.
+pub fn missing_pages(pages: &[PageCapture]) -> Vec<usize> {
+    (0..transcribe::identifiable_pages())
+        .filter(|page_id| !pages.iter().any(|page| page.page_id == *page_id))
+        .collect()
+}
+
+/// Page ids that were captured, but only in a transcription and so have no
+/// image.
+pub fn pages_without_images(pages: &[PageCapture]) -> Vec<usize> {
+    pages
+        .iter()
+        .filter(|page| page.image.is_none())
+        .map(|page| page.page_id)
+        .collect()
+}
+
+pub fn into_transcription(pages: &[PageCapture]) -> Transcription {
+    let mut data: Vec<Entry> = pages
+        .iter()
+        .flat_map(|page| page.entries.iter().cloned())
+        .collect();
+    data.sort_by_key(|entry| entry.uid);
+    Transcription { data }
+}
+
+/// The full book in page order, using blank pages where there is no image.
+pub fn page_images(pages: &[PageCapture]) -> Result<Vec<Image>, ImageError> {
+    let blank = crop::blank_page()?;
+    Ok((0..utils::page_metadata().len())
+        .map(|page_id| {
+            pages
+                .iter()
+                .find(|page| page.page_id == page_id)
+                .and_then(|page| page.image.clone())
+                .unwrap_or_else(|| blank.clone())
+        })
+        .collect())
+}
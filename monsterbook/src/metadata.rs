@@ -0,0 +1,207 @@
+use super::crop::{self, Image};
+use super::labels;
+use super::transcription::Transcription;
+use super::utils;
+use image::codecs::png::PngEncoder;
+use image::{ColorType, ImageError};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+// the header is always the first chunk, and text goes right after it so that
+// readers that stop at the image data still find it
+const HEADER_END: usize = 8 + 4 + 4 + 13 + 4;
+// standard keywords where there is one
+const SOFTWARE: &str = "Software";
+const AUTHOR: &str = "Author";
+const FILE_DATE: &str = "Screenshot File Date";
+const TRANSCRIPTION: &str = "Transcription";
+
+/// What a stitched image was made from, kept in its text chunks.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Metadata {
+    pub software: Option<String>,
+    pub character: Option<String>,
+    /// The date the newest screenshot was last modified, as YYYY-MM-DD. The
+    /// game does not record when a screenshot was taken.
+    pub file_date: Option<String>,
+    pub transcription: Option<Transcription>,
+}
+
+impl Metadata {
+    pub fn new(
+        character: Option<String>,
+        file_date: Option<String>,
+        transcription: Option<Transcription>,
+    ) -> Self {
+        Metadata {
+            software: Some(format!(
+                "{} {}",
+                env!("CARGO_PKG_NAME"),
+                env!("CARGO_PKG_VERSION")
+            )),
+            character,
+            file_date,
+            transcription,
+        }
+    }
+
+    fn text(&self) -> Vec<(&str, String)> {
+        let mut text = Vec::new();
+        let fields = [
+            (SOFTWARE, &self.software),
+            (AUTHOR, &self.character),
+            (FILE_DATE, &self.file_date),
+        ];
+        for (keyword, value) in fields {
+            if let Some(value) = value {
+                text.push((keyword, value.clone()));
+            }
+        }
+        if let Some(transcription) = &self.transcription {
+            text.push((TRANSCRIPTION, serde_json::to_string(transcription).unwrap()));
+        }
+        text
+    }
+}
+
+/// The newest modification date of the screenshots, or none without any.
+pub fn file_date<'a>(paths: impl IntoIterator<Item = &'a Path>) -> io::Result<Option<String>> {
+    let mut newest = None;
+    for path in paths {
+        newest = newest.max(Some(fs::metadata(path)?.modified()?));
+    }
+    Ok(newest.map(labels::date))
+}
+
+// an uncompressed international text chunk, so any utf-8 text is allowed
+fn text_chunk(keyword: &str, text: &str) -> Vec<u8> {
+    let mut body = b"iTXt".to_vec();
+    body.extend(keyword.as_bytes());
+    // no compression, no language tag and no translated keyword
+    body.extend([0, 0, 0, 0, 0]);
+    body.extend(text.as_bytes());
+    let mut chunk = ((body.len() - 4) as u32).to_be_bytes().to_vec();
+    chunk.extend(&body);
+    chunk.extend(utils::crc32(&body).to_be_bytes());
+    chunk
+}
+
+/// Encode an image as a png with the metadata in its text chunks.
+pub fn encode(img: &Image, metadata: &Metadata) -> Result<Vec<u8>, ImageError> {
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).encode(img.as_raw(), img.width(), img.height(), ColorType::Rgba8)?;
+    let chunks: Vec<u8> = metadata
+        .text()
+        .iter()
+        .flat_map(|(keyword, text)| text_chunk(keyword, text))
+        .collect();
+    png.splice(HEADER_END..HEADER_END, chunks);
+    Ok(png)
+}
+
+/// Save an image with the metadata when it is a png. Other formats are saved
+/// without it.
+pub fn imsave(output: &Path, img: &Image, metadata: &Metadata) -> Result<(), ImageError> {
+    if output.extension() != Some("png".as_ref()) {
+        return crop::imsave(output, img);
+    }
+    Ok(fs::write(output, encode(img, metadata)?)?)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// The text chunks of a png by keyword. Compressed text is skipped.
+pub fn text_chunks(png: &[u8]) -> io::Result<HashMap<String, String>> {
+    if !png.starts_with(&SIGNATURE) {
+        return Err(invalid("not a png"));
+    }
+    let mut text = HashMap::new();
+    let mut offset = SIGNATURE.len();
+    while offset + 8 <= png.len() {
+        let length = u32::from_be_bytes(png[offset..offset + 4].try_into().unwrap()) as usize;
+        let kind = &png[offset + 4..offset + 8];
+        let data = png
+            .get(offset + 8..offset + 8 + length)
+            .ok_or_else(|| invalid("truncated chunk"))?;
+        offset += 12 + length;
+        let mut fields = data.splitn(2, |byte| *byte == 0);
+        let (keyword, rest) = match (fields.next(), fields.next()) {
+            (Some(keyword), Some(rest)) => (String::from_utf8_lossy(keyword).into_owned(), rest),
+            _ => continue,
+        };
+        match kind {
+            // latin-1 text
+            b"tEXt" => {
+                text.insert(keyword, rest.iter().map(|byte| *byte as char).collect());
+            }
+            b"iTXt" if rest.first() == Some(&0) => {
+                // skip the flags, then the language tag and translated keyword
+                let value = rest
+                    .get(2..)
+                    .and_then(|rest| rest.splitn(3, |byte| *byte == 0).nth(2));
+                if let Some(value) = value {
+                    text.insert(keyword, String::from_utf8_lossy(value).into_owned());
+                }
+            }
+            _ => (),
+        }
+    }
+    Ok(text)
+}
+
+/// Read back the metadata of an image saved with `imsave`.
+pub fn read(path: &Path) -> io::Result<Metadata> {
+    let mut text = text_chunks(&fs::read(path)?)?;
+    let transcription = match text.remove(TRANSCRIPTION) {
+        Some(json) => Some(serde_json::from_str(&json)?),
+        None => None,
+    };
+    Ok(Metadata {
+        software: text.remove(SOFTWARE),
+        character: text.remove(AUTHOR),
+        file_date: text.remove(FILE_DATE),
+        transcription,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcription::Entry;
+    use image::Rgba;
+
+    #[test]
+    fn text_chunks_round_trip() {
+        let img = Image::from_pixel(3, 2, Rgba([10, 20, 30, 255]));
+        let transcription = Transcription {
+            data: vec![Entry {
+                uid: 0,
+                name: "Snail".into(),
+                count: 3,
+            }],
+        };
+        let metadata = Metadata::new(
+            Some("Zoë".into()),
+            Some("2021-05-01".into()),
+            Some(transcription.clone()),
+        );
+        let png = encode(&img, &metadata).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().to_rgba8(), img);
+        let text = text_chunks(&png).unwrap();
+        assert_eq!(text.len(), 4);
+        assert_eq!(text[AUTHOR], "Zoë");
+        assert_eq!(text[FILE_DATE], "2021-05-01");
+        assert_eq!(text[SOFTWARE], metadata.software.unwrap());
+        assert_eq!(
+            text[TRANSCRIPTION],
+            serde_json::to_string(&transcription).unwrap()
+        );
+        assert!(text_chunks(&png[1..]).is_err());
+    }
+}
@@ -0,0 +1,265 @@
+use super::crop::{into_grayscale_array, Image};
+use ndarray::Array2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// similarity scores are in [-1, 1], and are mapped onto [0, 10000] so that they
+// can share thresholds and orderings with the mse
+const SIMILARITY_SCALE: f64 = 5000.0;
+
+/// A comparison between two images of the same size. Lower is more similar.
+pub trait Metric {
+    /// Compare only the pixels where `keep` is set, indexed by row and column.
+    fn masked_distance(&self, img: &Image, reference: &Image, keep: &Array2<bool>) -> u32;
+
+    fn distance(&self, img: &Image, reference: &Image) -> u32 {
+        let keep = Array2::from_elem((img.height() as usize, img.width() as usize), true);
+        self.masked_distance(img, reference, &keep)
+    }
+}
+
+fn into_f64_array(img: &Image) -> Array2<f64> {
+    into_grayscale_array(img).mapv(|x| x as f64)
+}
+
+fn similarity_to_distance(similarity: f64) -> u32 {
+    ((1.0 - similarity.clamp(-1.0, 1.0)) * SIMILARITY_SCALE).round() as u32
+}
+
+// the values of both arrays where `keep` is set
+fn kept<'a>(
+    a: &'a Array2<f64>,
+    b: &'a Array2<f64>,
+    keep: &'a Array2<bool>,
+) -> impl Iterator<Item = (f64, f64)> + 'a {
+    a.iter()
+        .zip(b.iter())
+        .zip(keep.iter())
+        .filter(|(_, keep)| **keep)
+        .map(|((x, y), _)| (*x, *y))
+}
+
+fn mean_squared_error(a: &Array2<f64>, b: &Array2<f64>, keep: &Array2<bool>) -> u32 {
+    let (mut acc, mut n) = (0.0, 0);
+    for (x, y) in kept(a, b, keep) {
+        acc += (x - y).powi(2);
+        n += 1;
+    }
+    (acc / n.max(1) as f64) as u32
+}
+
+/// Mean squared error over grayscale pixels.
+pub struct Mse;
+
+impl Metric for Mse {
+    fn masked_distance(&self, img: &Image, reference: &Image, keep: &Array2<bool>) -> u32 {
+        mean_squared_error(&into_f64_array(img), &into_f64_array(reference), keep)
+    }
+}
+
+/// Zero-mean normalized cross-correlation, which ignores changes in brightness
+/// and contrast.
+pub struct Ncc;
+
+impl Metric for Ncc {
+    fn masked_distance(&self, img: &Image, reference: &Image, keep: &Array2<bool>) -> u32 {
+        let a = into_f64_array(img);
+        let b = into_f64_array(reference);
+        let n = kept(&a, &b, keep).count().max(1) as f64;
+        let (sum_a, sum_b) =
+            kept(&a, &b, keep).fold((0.0, 0.0), |(sa, sb), (x, y)| (sa + x, sb + y));
+        let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+        let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+        for (x, y) in kept(&a, &b, keep) {
+            cov += (x - mean_a) * (y - mean_b);
+            var_a += (x - mean_a).powi(2);
+            var_b += (y - mean_b).powi(2);
+        }
+        if var_a == 0.0 || var_b == 0.0 {
+            // flat images only correlate with themselves
+            let same = kept(&a, &b, keep).all(|(x, y)| x == y);
+            return similarity_to_distance(if same { 1.0 } else { 0.0 });
+        }
+        similarity_to_distance(cov / (var_a * var_b).sqrt())
+    }
+}
+
+/// Mean structural similarity over sliding windows. Windows that are not
+/// entirely kept are skipped.
+pub struct Ssim;
+
+impl Metric for Ssim {
+    fn masked_distance(&self, img: &Image, reference: &Image, keep: &Array2<bool>) -> u32 {
+        let a = into_f64_array(img);
+        let b = into_f64_array(reference);
+        let (c1, c2) = ((0.01f64 * 255.0).powi(2), (0.03f64 * 255.0).powi(2));
+        let (rows, cols) = a.dim();
+        // tags are smaller than the usual 7x7 window
+        let size = 7.min(rows).min(cols);
+        if size == 0 {
+            return 0;
+        }
+        let n = (size * size) as f64;
+        let mut total = 0.0;
+        let mut windows = 0;
+        for i in 0..=rows - size {
+            for j in 0..=cols - size {
+                let window = ndarray::s![i..i + size, j..j + size];
+                if keep.slice(window).iter().any(|keep| !keep) {
+                    continue;
+                }
+                let wa = a.slice(window);
+                let wb = b.slice(window);
+                let (mean_a, mean_b) = (wa.sum() / n, wb.sum() / n);
+                let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+                for (x, y) in wa.iter().zip(wb.iter()) {
+                    cov += (x - mean_a) * (y - mean_b);
+                    var_a += (x - mean_a).powi(2);
+                    var_b += (y - mean_b).powi(2);
+                }
+                let (cov, var_a, var_b) = (cov / n, var_a / n, var_b / n);
+                total += ((2.0 * mean_a * mean_b + c1) * (2.0 * cov + c2))
+                    / ((mean_a.powi(2) + mean_b.powi(2) + c1) * (var_a + var_b + c2));
+                windows += 1;
+            }
+        }
+        if windows == 0 {
+            // nothing left to compare, so there is no evidence of similarity
+            return similarity_to_distance(0.0);
+        }
+        similarity_to_distance(total / windows as f64)
+    }
+}
+
+/// Binary edge map in the same form as the python `sobel_filter`, where the
+/// sum of the horizontal and vertical responses is thresholded at zero.
+pub fn sobel(array: &Array2<f64>) -> Array2<f64> {
+    const KERNEL_X: [[f64; 3]; 3] = [[1.0, 0.0, -1.0], [2.0, 0.0, -2.0], [1.0, 0.0, -1.0]];
+    let (rows, cols) = array.dim();
+    let mut edges = Array2::zeros((rows, cols));
+    for i in 1..rows.saturating_sub(1) {
+        for j in 1..cols.saturating_sub(1) {
+            let mut acc = 0.0;
+            for di in 0..3 {
+                for dj in 0..3 {
+                    let value = array[[i + 1 - di, j + 1 - dj]];
+                    // the vertical kernel is the transpose of the horizontal one
+                    acc += value * (KERNEL_X[di][dj] + KERNEL_X[dj][di]);
+                }
+            }
+            edges[[i, j]] = if acc > 0.0 { 255.0 } else { 0.0 };
+        }
+    }
+    edges
+}
+
+/// Mean squared error between Sobel edge maps, as used by the web application
+/// to match pages. Edges next to a pixel that is not kept are skipped too.
+pub struct SobelMse;
+
+impl Metric for SobelMse {
+    fn masked_distance(&self, img: &Image, reference: &Image, keep: &Array2<bool>) -> u32 {
+        let (rows, cols) = keep.dim();
+        let keep = Array2::from_shape_fn((rows, cols), |(i, j)| {
+            (i.saturating_sub(1)..(i + 2).min(rows))
+                .all(|i| (j.saturating_sub(1)..(j + 2).min(cols)).all(|j| keep[[i, j]]))
+        });
+        mean_squared_error(
+            &sobel(&into_f64_array(img)),
+            &sobel(&into_f64_array(reference)),
+            &keep,
+        )
+    }
+}
+
+/// The available metrics, selectable by name.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricKind {
+    #[default]
+    Mse,
+    Ncc,
+    Ssim,
+    Sobel,
+}
+
+impl MetricKind {
+    pub fn metric(&self) -> &'static dyn Metric {
+        match self {
+            MetricKind::Mse => &Mse,
+            MetricKind::Ncc => &Ncc,
+            MetricKind::Ssim => &Ssim,
+            MetricKind::Sobel => &SobelMse,
+        }
+    }
+
+    pub fn distance(&self, img: &Image, reference: &Image) -> u32 {
+        self.metric().distance(img, reference)
+    }
+}
+
+impl FromStr for MetricKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "mse" => Ok(MetricKind::Mse),
+            "ncc" => Ok(MetricKind::Ncc),
+            "ssim" => Ok(MetricKind::Ssim),
+            "sobel" => Ok(MetricKind::Sobel),
+            _ => Err(format!(
+                "unknown metric {}, expected one of mse, ncc, ssim, sobel",
+                name
+            )),
+        }
+    }
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            MetricKind::Mse => "mse",
+            MetricKind::Ncc => "ncc",
+            MetricKind::Ssim => "ssim",
+            MetricKind::Sobel => "sobel",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn masked_pixels_do_not_count() {
+        let reference = RgbaImage::from_fn(24, 24, |x, y| {
+            Rgba([(x * 10) as u8, (y * 10) as u8, 0, 255])
+        });
+        let mut img = reference.clone();
+        let mut keep = Array2::from_elem((24, 24), true);
+        for y in 0..8 {
+            for x in 0..8 {
+                img.put_pixel(x, y, Rgba([255, 255, 255, 255]));
+                keep[[y as usize, x as usize]] = false;
+            }
+        }
+        for kind in [
+            MetricKind::Mse,
+            MetricKind::Ncc,
+            MetricKind::Ssim,
+            MetricKind::Sobel,
+        ] {
+            let metric = kind.metric();
+            assert_eq!(
+                metric.masked_distance(&img, &reference, &keep),
+                metric.distance(&reference, &reference),
+                "{}",
+                kind
+            );
+            assert!(metric.distance(&img, &reference) > metric.distance(&reference, &reference));
+        }
+    }
+}
@@ -0,0 +1,101 @@
+use super::arrange::{self, Arrangement};
+use super::catalog;
+use super::crop::{self, Image};
+use super::font;
+use super::labels::{self, Labels};
+use super::stitch::{Limits, StitchOptions};
+use super::transcribe;
+use super::transcription::{Transcription, MAX_COUNT};
+use super::upscale::{self, Upscale};
+use super::utils;
+use image::ImageError;
+use std::collections::HashMap;
+
+// characters of text that fit beside each card
+const COLUMNS: u32 = 24;
+
+/// A card that is short of a full set, with the artwork to show for it.
+pub struct MissingCard {
+    pub uid: usize,
+    pub count: u8,
+    pub card: Image,
+}
+
+/// Every card in the book that is not complete. Cards the player has seen use
+/// their own crop when there is one, the rest use the reference book, or an
+/// empty card where there is no reference.
+pub fn missing_cards(
+    transcription: &Transcription,
+    seen: &HashMap<usize, Image>,
+) -> Result<Vec<MissingCard>, ImageError> {
+    let counts = transcription.counts();
+    let references: HashMap<usize, Image> = transcribe::reference_cards()?.into_iter().collect();
+    let empty_card = crop::get_empty_card()?;
+    Ok((0..catalog::monsters().len())
+        .filter_map(|uid| {
+            let count = counts.get(&uid).copied().unwrap_or(0);
+            if count >= MAX_COUNT {
+                return None;
+            }
+            let card = match seen.get(&uid) {
+                Some(card) if count > 0 => card,
+                _ => references.get(&uid).unwrap_or(&empty_card),
+            };
+            Some(MissingCard {
+                uid,
+                count,
+                card: card.clone(),
+            })
+        })
+        .collect())
+}
+
+/// A checklist of missing cards, each with its name, map and count beside it.
+/// Captions are ignored.
+pub fn poster(
+    cards: Vec<MissingCard>,
+    width: u32,
+    options: &StitchOptions,
+    limits: &Limits,
+    arrangement: &Arrangement,
+    labels: &Labels,
+    upscale: &Upscale,
+) -> Vec<Image> {
+    let monsters = catalog::monsters();
+    let counts: HashMap<usize, u8> = cards.iter().map(|card| (card.uid, card.count)).collect();
+    let cards: Vec<MissingCard> = cards
+        .into_iter()
+        .map(|missing| MissingCard {
+            card: upscale::upscale(&missing.card, upscale),
+            ..missing
+        })
+        .collect();
+    // player crops and reference cards can differ in size, so fit the widest
+    let tile_width = cards
+        .iter()
+        .map(|card| card.card.width())
+        .max()
+        .unwrap_or(0)
+        + font::text_width(&" ".repeat(COLUMNS as usize + 1), 1);
+    let tiles = cards
+        .into_iter()
+        .map(|missing| {
+            let (name, map) = match monsters.get(missing.uid) {
+                Some(monster) => (monster.name.clone(), monster.map.clone()),
+                None => (format!("#{}", missing.uid), String::new()),
+            };
+            let status = match missing.count {
+                0 => "unseen".to_string(),
+                count => format!("{}/{}", count, MAX_COUNT),
+            };
+            let lines = [name, map, status];
+            let tile = labels::beside(&missing.card, &lines, tile_width, options.background);
+            (missing.uid, tile)
+        })
+        .collect();
+    let sections = arrange::arrange(tiles, arrangement, Some(&counts));
+    utils::split_sections(sections, width, limits, |sections, width| {
+        let stitched = utils::stitch_sections(sections, width, options, labels.headings);
+        labels::with_header(&stitched, &labels.header, options.background)
+    })
+}
@@ -0,0 +1,181 @@
+use super::crop::{self, Image, ARTWORK, TAG};
+use super::metrics::MetricKind;
+use image::{imageops, GrayImage, ImageError, Luma};
+use nshare::ToNdarray2;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Pixels set to 255 are occluded and left out of comparisons.
+pub type Mask = GrayImage;
+
+// roughly the size of the in-game cursor
+const CURSOR: (u32, u32) = (16, 24);
+// the largest channel difference that is still considered part of the frame
+const TOLERANCE: i32 = 60;
+// a handful of stray pixels are not enough to call a card occluded
+const MIN_PIXELS: usize = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Occlusion {
+    Cursor,
+    Tooltip,
+}
+
+impl fmt::Display for Occlusion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Occlusion::Cursor => write!(f, "cursor"),
+            Occlusion::Tooltip => write!(f, "tooltip"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Occluded {
+    pub kind: Occlusion,
+    pub mask: Mask,
+}
+
+impl Occluded {
+    /// Whether the count tag is hidden, which makes the count unreliable.
+    pub fn covers_tag(&self) -> bool {
+        let (x, y, w, h) = TAG;
+        (x..x + w).any(|i| (y..y + h).any(|j| self.mask.get_pixel(i, j)[0] > 0))
+    }
+}
+
+fn in_frame(x: u32, y: u32) -> bool {
+    let (ix, iy, iw, ih) = ARTWORK;
+    x < ix || x >= ix + iw || y < iy || y >= iy + ih
+}
+
+fn deviates(card: &Image, template: &Image, x: u32, y: u32) -> bool {
+    let (a, b) = (card.get_pixel(x, y), template.get_pixel(x, y));
+    (0..3).any(|c| (a[c] as i32 - b[c] as i32).abs() > TOLERANCE)
+}
+
+// the frame around an unselected card is the same as the empty card, while
+// the selected card has a highlight ring
+fn frame_templates() -> Result<Vec<Image>, ImageError> {
+    Ok(vec![crop::get_empty_card()?, crop::get_selected_card()?])
+}
+
+fn frame_deviations(card: &Image, template: &Image) -> Vec<(u32, u32)> {
+    card.enumerate_pixels()
+        .map(|(x, y, _)| (x, y))
+        .filter(|(x, y)| in_frame(*x, *y) && deviates(card, template, *x, *y))
+        .collect()
+}
+
+/// Detect a cursor or tooltip over a card by looking for pixels in the frame
+/// around the artwork that do not match the expected frame. Anything that lies
+/// entirely over the artwork cannot be told apart from the artwork itself.
+pub fn detect(card: &Image, templates: &[Image]) -> Option<Occluded> {
+    let deviations = templates
+        .iter()
+        .map(|template| frame_deviations(card, template))
+        .min_by_key(|deviations| deviations.len())?;
+    if deviations.len() < MIN_PIXELS {
+        return None;
+    }
+    let min_x = deviations.iter().map(|(x, _)| *x).min()?;
+    let max_x = deviations.iter().map(|(x, _)| *x).max()?;
+    let min_y = deviations.iter().map(|(_, y)| *y).min()?;
+    let max_y = deviations.iter().map(|(_, y)| *y).max()?;
+    // a single line is an odd frame rather than something on top of the card
+    if min_x == max_x || min_y == max_y {
+        return None;
+    }
+
+    let frame_size = card
+        .enumerate_pixels()
+        .filter(|(x, y, _)| in_frame(*x, *y))
+        .count();
+    let (width, height) = card.dimensions();
+    let mut mask = Mask::new(width, height);
+    let kind = if deviations.len() * 2 > frame_size {
+        // tooltips are large, so nothing on the card can be trusted
+        for pixel in mask.pixels_mut() {
+            *pixel = Luma([255]);
+        }
+        Occlusion::Tooltip
+    } else {
+        // the cursor may reach into the artwork from where it touches the frame
+        let (cw, ch) = CURSOR;
+        let (x0, y0) = (min_x.saturating_sub(cw), min_y.saturating_sub(ch));
+        let (x1, y1) = ((max_x + cw).min(width - 1), (max_y + ch).min(height - 1));
+        for x in x0..=x1 {
+            for y in y0..=y1 {
+                mask.put_pixel(x, y, Luma([255]));
+            }
+        }
+        Occlusion::Cursor
+    };
+    Some(Occluded { kind, mask })
+}
+
+/// Detect occlusions over every card of a cropped page, by card index.
+pub fn detect_page(page: &Image) -> Result<Vec<Option<Occluded>>, ImageError> {
+    let templates = frame_templates()?;
+    Ok(crop::crop_cards(page)?
+        .iter()
+        .map(|card| detect(card, &templates))
+        .collect())
+}
+
+/// Combine the masks of each card into a mask over the whole page.
+pub fn page_mask(page: &Image, occlusions: &[Option<Occluded>]) -> Mask {
+    let (num_rows, num_cols) = (5, 5);
+    let (w, h) = (page.width() / num_cols, page.height() / num_rows);
+    let mut mask = Mask::new(page.width(), page.height());
+    for (i, occluded) in occlusions.iter().enumerate() {
+        if let Some(occluded) = occluded {
+            let (x, y) = ((i as u32 % num_cols) * w, (i as u32 / num_cols) * h);
+            imageops::overlay(&mut mask, &occluded.mask, x, y);
+        }
+    }
+    mask
+}
+
+/// Distance over the pixels that are not masked.
+pub fn masked_distance(metric: MetricKind, img: &Image, reference: &Image, mask: &Mask) -> u32 {
+    let keep = mask.clone().into_ndarray2().mapv(|pixel| pixel == 0);
+    // with nothing left to compare, fall back to the whole image
+    if keep.iter().all(|keep| *keep) || !keep.iter().any(|keep| *keep) {
+        return metric.distance(img, reference);
+    }
+    metric.metric().masked_distance(img, reference, &keep)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn plain_frames_are_not_occluded() {
+        let templates = frame_templates().unwrap();
+        for card in &templates {
+            let deviations = templates
+                .iter()
+                .map(|template| frame_deviations(card, template).len())
+                .min();
+            assert_eq!(deviations, Some(0));
+            assert!(detect(card, &templates).is_none());
+        }
+    }
+
+    #[test]
+    fn a_cursor_on_the_frame_is_occluded() {
+        let templates = frame_templates().unwrap();
+        let mut card = crop::get_empty_card().unwrap();
+        for x in 0..6 {
+            for y in 0..8 {
+                card.put_pixel(x, y, Rgba([255, 0, 255, 255]));
+            }
+        }
+        let occluded = detect(&card, &templates).unwrap();
+        assert_eq!(occluded.kind, Occlusion::Cursor);
+        assert_eq!(occluded.mask.get_pixel(0, 0)[0], 255);
+    }
+}
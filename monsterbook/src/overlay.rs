@@ -0,0 +1,184 @@
+use super::crop::Image;
+use super::font;
+use super::transcription::MAX_COUNT;
+use image::{imageops, Rgba};
+use std::collections::HashMap;
+use std::str::FromStr;
+
+// how much of the heatmap color is mixed into a card
+const TINT: f32 = 0.35;
+// how much darker an incomplete card is when dimmed
+const DIM: f32 = 0.5;
+const BADGE: Rgba<u8> = Rgba([32, 32, 32, 255]);
+const BADGE_COMPLETE: Rgba<u8> = Rgba([204, 153, 0, 255]);
+const BADGE_TEXT: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+/// How cards that are not yet complete are drawn.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Incomplete {
+    #[default]
+    Keep,
+    Dim,
+    Grayscale,
+}
+
+impl FromStr for Incomplete {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "keep" => Ok(Incomplete::Keep),
+            "dim" => Ok(Incomplete::Dim),
+            "grayscale" => Ok(Incomplete::Grayscale),
+            _ => Err(format!(
+                "unknown mode {}, expected one of keep, dim, grayscale",
+                s
+            )),
+        }
+    }
+}
+
+/// Progress drawn onto each card. Nothing is drawn without counts.
+#[derive(Clone, Debug, Default)]
+pub struct Overlay {
+    /// Card counts keyed by uid, see `Transcription::counts`
+    pub counts: Option<HashMap<usize, u8>>,
+    pub badges: bool,
+    pub incomplete: Incomplete,
+    pub heatmap: bool,
+}
+
+/// A color from red for no cards to green for a complete set.
+pub fn heat(count: u8) -> Rgba<u8> {
+    let t = count.min(MAX_COUNT) as f32 / MAX_COUNT as f32;
+    Rgba([(255.0 * (1.0 - t)) as u8, (64.0 + 191.0 * t) as u8, 64, 255])
+}
+
+fn blend(pixel: &mut Rgba<u8>, color: Rgba<u8>, amount: f32) {
+    for c in 0..3 {
+        pixel[c] = (pixel[c] as f32 * (1.0 - amount) + color[c] as f32 * amount) as u8;
+    }
+}
+
+// a box in the top right corner with the count in it
+fn draw_badge(card: &mut Image, count: u8) {
+    let (w, h) = (font::GLYPH_WIDTH + 2, font::GLYPH_HEIGHT + 2);
+    let (x, y) = (card.width() - w - 1, 1);
+    let color = if count >= MAX_COUNT {
+        BADGE_COMPLETE
+    } else {
+        BADGE
+    };
+    for j in y..y + h {
+        for i in x..x + w {
+            card.put_pixel(i, j, color);
+        }
+    }
+    font::draw_text(card, x + 1, y + 1, &count.to_string(), BADGE_TEXT, 1);
+}
+
+/// Draw the progress of a single card.
+pub fn apply(card: &mut Image, uid: usize, overlay: &Overlay) {
+    let counts = match &overlay.counts {
+        Some(counts) => counts,
+        None => return,
+    };
+    let count = counts.get(&uid).copied().unwrap_or(0);
+    if count < MAX_COUNT {
+        match overlay.incomplete {
+            Incomplete::Keep => (),
+            Incomplete::Dim => {
+                for pixel in card.pixels_mut() {
+                    blend(pixel, Rgba([0, 0, 0, 255]), DIM);
+                }
+            }
+            Incomplete::Grayscale => {
+                let gray = imageops::grayscale(card);
+                for (pixel, luma) in card.pixels_mut().zip(gray.pixels()) {
+                    *pixel = Rgba([luma[0], luma[0], luma[0], pixel[3]]);
+                }
+            }
+        }
+    }
+    if overlay.heatmap {
+        let color = heat(count);
+        for pixel in card.pixels_mut() {
+            blend(pixel, color, TINT);
+        }
+    }
+    if overlay.badges {
+        draw_badge(card, count);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn card() -> Image {
+        Image::from_pixel(33, 45, Rgba([200, 100, 50, 255]))
+    }
+
+    fn overlay(count: u8, incomplete: Incomplete) -> Overlay {
+        Overlay {
+            counts: Some(HashMap::from([(0, count)])),
+            incomplete,
+            ..Overlay::default()
+        }
+    }
+
+    #[test]
+    fn heat_runs_from_red_to_green() {
+        assert_eq!(heat(0), Rgba([255, 64, 64, 255]));
+        assert_eq!(heat(MAX_COUNT), Rgba([0, 255, 64, 255]));
+        assert_eq!(heat(MAX_COUNT + 1), heat(MAX_COUNT));
+    }
+
+    #[test]
+    fn nothing_is_drawn_without_counts() {
+        let mut img = card();
+        let overlay = Overlay {
+            badges: true,
+            heatmap: true,
+            incomplete: Incomplete::Dim,
+            ..Overlay::default()
+        };
+        apply(&mut img, 0, &overlay);
+        assert_eq!(img, card());
+    }
+
+    #[test]
+    fn incomplete_cards_are_dimmed_or_grayed() {
+        let mut img = card();
+        apply(&mut img, 0, &overlay(2, Incomplete::Dim));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([100, 50, 25, 255]));
+
+        let mut img = card();
+        apply(&mut img, 0, &overlay(2, Incomplete::Grayscale));
+        let pixel = img.get_pixel(0, 0);
+        assert!(pixel[0] == pixel[1] && pixel[1] == pixel[2]);
+
+        // complete cards are left alone, and cards without a count are incomplete
+        let mut img = card();
+        apply(&mut img, 0, &overlay(MAX_COUNT, Incomplete::Dim));
+        assert_eq!(img, card());
+        let mut img = card();
+        apply(&mut img, 1, &overlay(MAX_COUNT, Incomplete::Dim));
+        assert_eq!(*img.get_pixel(0, 0), Rgba([100, 50, 25, 255]));
+    }
+
+    #[test]
+    fn badges_are_gold_for_complete_sets() {
+        let corner = (33 - font::GLYPH_WIDTH - 3, 1);
+        for (count, color) in [(2, BADGE), (MAX_COUNT, BADGE_COMPLETE)] {
+            let mut img = card();
+            let overlay = Overlay {
+                badges: true,
+                ..overlay(count, Incomplete::Keep)
+            };
+            apply(&mut img, 0, &overlay);
+            assert_eq!(*img.get_pixel(corner.0, corner.1), color);
+            assert_eq!(*img.get_pixel(0, 0), *card().get_pixel(0, 0));
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use super::utils;
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+pub const THEMES: [&str; 3] = ["in-game", "pastel", "colorblind"];
+
+/// Background colors for the cards of each tab, keyed by tab color. Theme
+/// files are json objects of the form `{"red": [255, 102, 102], ...}`.
+#[derive(Clone, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(transparent)]
+pub struct Palette {
+    pub colors: BTreeMap<String, [u8; 3]>,
+}
+
+impl Palette {
+    fn from_colors(colors: [[u8; 3]; 9]) -> Self {
+        Palette {
+            colors: utils::tab_colors().into_iter().zip(colors).collect(),
+        }
+    }
+
+    /// The colors of the old web `get_color` table. They were picked by hand and
+    /// have not been sampled from the game client.
+    pub fn in_game() -> Self {
+        Palette::from_colors([
+            [255, 102, 102],
+            [255, 187, 68],
+            [221, 255, 102],
+            [102, 255, 136],
+            [136, 255, 238],
+            [119, 187, 255],
+            [187, 119, 255],
+            [85, 85, 85],
+            [255, 187, 34],
+        ])
+    }
+
+    /// The in-game colors, halfway to white.
+    pub fn pastel() -> Self {
+        let mut palette = Palette::in_game();
+        for color in palette.colors.values_mut() {
+            for channel in color.iter_mut() {
+                *channel = ((*channel as u16 + 255) / 2) as u8;
+            }
+        }
+        palette
+    }
+
+    /// The Okabe-Ito palette, which stays distinct under common forms of color
+    /// blindness, with gray for the ninth tab.
+    pub fn colorblind() -> Self {
+        Palette::from_colors([
+            [213, 94, 0],
+            [230, 159, 0],
+            [240, 228, 66],
+            [0, 158, 115],
+            [86, 180, 233],
+            [0, 114, 178],
+            [204, 121, 167],
+            [0, 0, 0],
+            [153, 153, 153],
+        ])
+    }
+
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+
+    /// The color for a tab, or `None` if the palette does not cover it.
+    pub fn color(&self, tab_color: &str) -> Option<Rgba<u8>> {
+        self.colors
+            .get(tab_color)
+            .map(|[r, g, b]| Rgba([*r, *g, *b, 255]))
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette::in_game()
+    }
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "in-game" => Ok(Palette::in_game()),
+            "pastel" => Ok(Palette::pastel()),
+            "colorblind" => Ok(Palette::colorblind()),
+            _ => Err(format!(
+                "unknown theme {}, expected one of {}",
+                s,
+                THEMES.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+use super::catalog::{self, Monster};
+use super::transcription::{Transcription, MAX_COUNT};
+
+pub struct Target {
+    pub uid: usize,
+    pub name: String,
+    pub count: u8,
+}
+
+/// A single map to visit on the farming route.
+pub struct Stop {
+    pub town: String,
+    pub map: String,
+    pub tour_order: u32,
+    pub targets: Vec<Target>,
+}
+
+impl Stop {
+    /// Number of cards left to collect at this stop.
+    pub fn remaining(&self) -> u32 {
+        self.targets
+            .iter()
+            .map(|target| (MAX_COUNT - target.count) as u32)
+            .sum()
+    }
+}
+
+fn find_stop<'a>(stops: &'a mut Vec<Stop>, monster: &Monster) -> &'a mut Stop {
+    let index = stops
+        .iter()
+        .position(|stop| stop.town == monster.town && stop.map == monster.map);
+    match index {
+        Some(i) => &mut stops[i],
+        None => {
+            stops.push(Stop {
+                town: monster.town.clone(),
+                map: monster.map.clone(),
+                tour_order: monster.tour_order,
+                targets: Vec::new(),
+            });
+            stops.last_mut().unwrap()
+        }
+    }
+}
+
+/// Build an itinerary of maps in tour order, skipping completed cards.
+pub fn plan(transcription: &Transcription) -> Vec<Stop> {
+    let counts = transcription.counts();
+    let mut stops = Vec::new();
+    for (uid, monster) in catalog::monsters().iter().enumerate() {
+        let count = *counts.get(&uid).unwrap_or(&0);
+        if count >= MAX_COUNT {
+            continue;
+        }
+        let stop = find_stop(&mut stops, monster);
+        // maps shared by several monsters may have slightly different orders
+        stop.tour_order = stop.tour_order.min(monster.tour_order);
+        stop.targets.push(Target {
+            uid,
+            name: monster.name.clone(),
+            count,
+        });
+    }
+    stops.sort_by(|a, b| (a.tour_order, &a.town, &a.map).cmp(&(b.tour_order, &b.town, &b.map)));
+    stops
+}
@@ -0,0 +1,105 @@
+use super::calibrate::Calibration;
+use super::crop::{self, Image};
+use super::transcribe;
+use image::ImageError;
+use std::fmt;
+
+// crops of the same screenshot are nearly identical
+pub const DUPLICATE_THRESHOLD: u32 = 10;
+
+/// Two screenshots that were identified as the same page.
+#[derive(Clone)]
+pub struct Duplicate {
+    pub page_id: usize,
+    pub first: usize,
+    pub second: usize,
+    /// Whether the crops are near identical, rather than a possible misread
+    pub identical: bool,
+}
+
+/// The result of identifying every page before stitching.
+#[derive(Clone)]
+pub struct PageCheck {
+    /// The identified page for each image, in input order
+    pub page_ids: Vec<usize>,
+    pub missing: Vec<usize>,
+    pub duplicates: Vec<Duplicate>,
+}
+
+impl PageCheck {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.duplicates.is_empty()
+    }
+}
+
+impl fmt::Display for PageCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} images", self.page_ids.len())?;
+        if !self.missing.is_empty() {
+            writeln!(f, "missing pages: {:?}", self.missing)?;
+        }
+        for duplicate in &self.duplicates {
+            writeln!(
+                f,
+                "images {} and {} are both page {}{}",
+                duplicate.first,
+                duplicate.second,
+                duplicate.page_id,
+                if duplicate.identical {
+                    ""
+                } else {
+                    " but differ, one may be misidentified"
+                }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Identify every page the way transcription does, with the page metric and
+/// denoising of the calibration.
+pub fn check_pages(images: &[Image], calibration: &Calibration) -> Result<PageCheck, ImageError> {
+    let references = transcribe::reference_pages()?;
+    let page_ids: Vec<usize> = images
+        .iter()
+        .map(|img| {
+            let compared = calibration.compared(img);
+            transcribe::best_match(&compared, &references, calibration.page_metric)
+        })
+        .collect();
+    let missing = (0..references.len())
+        .filter(|page_id| !page_ids.contains(page_id))
+        .collect();
+    let mut duplicates = Vec::new();
+    for (second, page_id) in page_ids.iter().enumerate() {
+        if let Some(first) = page_ids[..second].iter().position(|id| id == page_id) {
+            duplicates.push(Duplicate {
+                page_id: *page_id,
+                first,
+                second,
+                identical: crop::mse(&images[first], &images[second]) < DUPLICATE_THRESHOLD,
+            });
+        }
+    }
+    Ok(PageCheck {
+        page_ids,
+        missing,
+        duplicates,
+    })
+}
+
+/// Order the images by page, keeping the first image of each page and
+/// filling in missing pages with blanks.
+pub fn arrange_pages(images: &[Image], check: &PageCheck) -> Result<Vec<Image>, ImageError> {
+    let blank = crop::blank_page()?;
+    Ok((0..transcribe::identifiable_pages())
+        .map(|page_id| {
+            check
+                .page_ids
+                .iter()
+                .position(|id| *id == page_id)
+                .map(|index| images[index].clone())
+                .unwrap_or_else(|| blank.clone())
+        })
+        .collect())
+}
@@ -0,0 +1,159 @@
+use super::crop::{self, Image};
+use image::{imageops, ImageError, Rgba};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// below this the tags start to smear into each other
+pub const MIN_JPEG_QUALITY: u8 = 70;
+// ratio of the gradient across block edges to the gradient within blocks
+pub const MAX_BLOCKINESS: f64 = 1.5;
+
+// the luminance quantization table from the jpeg specification, at quality 50,
+// in zigzag order to match the DQT segment
+#[rustfmt::skip]
+const STANDARD_LUMINANCE: [u16; 64] = [
+    16, 11, 12, 14, 12, 10, 16, 14,
+    13, 14, 18, 17, 16, 19, 24, 40,
+    26, 24, 22, 22, 24, 49, 35, 37,
+    29, 40, 58, 51, 61, 60, 57, 51,
+    56, 55, 64, 72, 92, 78, 64, 68,
+    87, 69, 55, 56, 80, 109, 81, 87,
+    95, 98, 103, 104, 103, 62, 77, 113,
+    121, 112, 100, 120, 92, 101, 103, 99,
+];
+
+/// Estimate the quality setting of a jpeg from its luminance quantization
+/// table, using the scaling from the IJG encoder. Returns `None` for other
+/// formats.
+pub fn jpeg_quality(bytes: &[u8]) -> Option<u8> {
+    if bytes.len() < 4 || bytes[0..2] != [0xFF, 0xD8] {
+        return None;
+    }
+    let mut i = 2;
+    while i + 4 <= bytes.len() {
+        if bytes[i] != 0xFF {
+            return None;
+        }
+        let marker = bytes[i + 1];
+        let length = u16::from_be_bytes([bytes[i + 2], bytes[i + 3]]) as usize;
+        // the start of scan means we have passed all of the tables
+        if marker == 0xDA {
+            return None;
+        }
+        if marker == 0xDB {
+            let mut j = i + 4;
+            while j < i + 2 + length && j < bytes.len() {
+                let precision = bytes[j] >> 4;
+                let table_id = bytes[j] & 0x0F;
+                let size = if precision == 0 { 64 } else { 128 };
+                if table_id == 0 && j + 1 + size <= bytes.len() {
+                    let values = (0..64).map(|k| {
+                        if precision == 0 {
+                            bytes[j + 1 + k] as f64
+                        } else {
+                            u16::from_be_bytes([bytes[j + 1 + 2 * k], bytes[j + 2 + 2 * k]]) as f64
+                        }
+                    });
+                    let scale = values
+                        .zip(STANDARD_LUMINANCE.iter())
+                        .map(|(q, s)| q * 100.0 / *s as f64)
+                        .sum::<f64>()
+                        / 64.0;
+                    let quality = if scale <= 100.0 {
+                        (200.0 - scale) / 2.0
+                    } else {
+                        5000.0 / scale
+                    };
+                    return Some(quality.round().clamp(1.0, 100.0) as u8);
+                }
+                j += 1 + size;
+            }
+        }
+        i += 2 + length;
+    }
+    None
+}
+
+/// Compare the gradient across the edges of the 8x8 jpeg blocks against the
+/// gradient inside of them. A clean image is close to 1.
+pub fn blockiness(img: &Image) -> f64 {
+    let gray = imageops::grayscale(img);
+    let (width, height) = gray.dimensions();
+    let (mut edge, mut edge_n, mut inner, mut inner_n) = (0.0, 0, 0.0, 0);
+    for y in 0..height {
+        for x in 1..width {
+            let diff = (gray.get_pixel(x, y)[0] as f64 - gray.get_pixel(x - 1, y)[0] as f64).abs();
+            if x % 8 == 0 {
+                edge += diff;
+                edge_n += 1;
+            } else {
+                inner += diff;
+                inner_n += 1;
+            }
+        }
+    }
+    for y in 1..height {
+        for x in 0..width {
+            let diff = (gray.get_pixel(x, y)[0] as f64 - gray.get_pixel(x, y - 1)[0] as f64).abs();
+            if y % 8 == 0 {
+                edge += diff;
+                edge_n += 1;
+            } else {
+                inner += diff;
+                inner_n += 1;
+            }
+        }
+    }
+    if edge_n == 0 || inner == 0.0 {
+        return 1.0;
+    }
+    (edge / edge_n as f64) / (inner / inner_n as f64)
+}
+
+pub struct QualityReport {
+    pub path: PathBuf,
+    pub jpeg_quality: Option<u8>,
+    pub blockiness: f64,
+}
+
+impl QualityReport {
+    /// Whether the screenshot is likely too degraded to read the counts.
+    pub fn is_degraded(&self) -> bool {
+        matches!(self.jpeg_quality, Some(quality) if quality < MIN_JPEG_QUALITY)
+            || self.blockiness > MAX_BLOCKINESS
+    }
+}
+
+pub fn assess(path: &Path) -> Result<QualityReport, ImageError> {
+    let bytes = fs::read(path)?;
+    // block edges are aligned to the full screenshot, not the cropped page
+    let img = crop::imdecode(&bytes)?;
+    Ok(QualityReport {
+        path: path.to_path_buf(),
+        jpeg_quality: jpeg_quality(&bytes),
+        blockiness: blockiness(&img),
+    })
+}
+
+/// A light 3x3 median filter, which removes jpeg speckle while keeping the
+/// edges of the artwork and tags.
+pub fn denoise(img: &Image) -> Image {
+    let (width, height) = img.dimensions();
+    let mut output = img.clone();
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let mut pixel = [0; 4];
+            for (c, value) in pixel.iter_mut().enumerate() {
+                let mut window = [0u8; 9];
+                for (k, item) in window.iter_mut().enumerate() {
+                    let (dx, dy) = (k as u32 % 3, k as u32 / 3);
+                    *item = img.get_pixel(x + dx - 1, y + dy - 1)[c];
+                }
+                window.sort_unstable();
+                *value = window[4];
+            }
+            output.put_pixel(x, y, Rgba(pixel));
+        }
+    }
+    output
+}
@@ -0,0 +1,167 @@
+use super::catalog;
+use super::crop::{Image, ARTWORK};
+use super::metrics::MetricKind;
+use super::transcribe::{self, TranscribedPage};
+use image::{imageops, ImageError};
+use serde::Serialize;
+
+// hashes further apart than this are treated as different monsters
+pub const MAX_HAMMING: u32 = 12;
+// candidates this close to the best hash are ranked by the metric instead
+const SLACK: u32 = 4;
+
+/// A 64 bit difference hash of the artwork on a card.
+pub type Hash = u64;
+
+fn artwork(card: &Image) -> Image {
+    let (x, y, w, h) = ARTWORK;
+    let mut card = card.clone();
+    imageops::crop(&mut card, x, y, w, h).to_image()
+}
+
+/// Hash the artwork by comparing neighbouring pixels of a 9x8 thumbnail. Only
+/// the direction of each gradient is kept, so the faded artwork in the
+/// reference book hashes close to the same monster in full color.
+pub fn hash(card: &Image) -> Hash {
+    let gray = imageops::grayscale(&artwork(card));
+    let thumb = imageops::resize(&gray, 9, 8, imageops::FilterType::Triangle);
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash <<= 1;
+            if thumb.get_pixel(x, y)[0] < thumb.get_pixel(x + 1, y)[0] {
+                hash |= 1;
+            }
+        }
+    }
+    hash
+}
+
+pub fn hamming(a: Hash, b: Hash) -> u32 {
+    (a ^ b).count_ones()
+}
+
+struct IndexEntry {
+    uid: usize,
+    hash: Hash,
+    artwork: Image,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Recognition {
+    pub uid: usize,
+    pub hamming: u32,
+    pub distance: u32,
+}
+
+/// Cards with a known uid that new cards are matched against.
+pub struct CardIndex {
+    metric: MetricKind,
+    entries: Vec<IndexEntry>,
+}
+
+impl CardIndex {
+    pub fn new(metric: MetricKind) -> Self {
+        CardIndex {
+            metric,
+            entries: Vec::new(),
+        }
+    }
+
+    /// An index of every card in the reference book.
+    pub fn reference_book(metric: MetricKind) -> Result<Self, ImageError> {
+        let mut index = CardIndex::new(metric);
+        for (uid, card) in transcribe::reference_cards()? {
+            index.insert(uid, &card);
+        }
+        Ok(index)
+    }
+
+    pub fn insert(&mut self, uid: usize, card: &Image) {
+        self.entries.push(IndexEntry {
+            uid,
+            hash: hash(card),
+            artwork: artwork(card),
+        });
+    }
+
+    /// Add the seen cards of pages that have already been checked by hand.
+    pub fn insert_pages(&mut self, pages: &[TranscribedPage]) {
+        for page in pages {
+            for (entry, card) in &page.cards {
+                if entry.count > 0 {
+                    self.insert(entry.uid, card);
+                }
+            }
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Identify the monster on a card, or `None` when nothing in the index is
+    /// close enough.
+    pub fn recognize(&self, card: &Image) -> Option<Recognition> {
+        let card_hash = hash(card);
+        let best = self
+            .entries
+            .iter()
+            .map(|entry| hamming(card_hash, entry.hash))
+            .min()?;
+        if best > MAX_HAMMING {
+            return None;
+        }
+        let card_artwork = artwork(card);
+        self.entries
+            .iter()
+            .filter(|entry| hamming(card_hash, entry.hash) <= best + SLACK)
+            .map(|entry| Recognition {
+                uid: entry.uid,
+                hamming: hamming(card_hash, entry.hash),
+                distance: self.metric.distance(&card_artwork, &entry.artwork),
+            })
+            .min_by_key(|recognition| (recognition.distance, recognition.hamming))
+    }
+}
+
+/// A card whose artwork looks like a different monster than its position says.
+#[derive(Clone, Debug, Serialize)]
+pub struct Mismatch {
+    pub uid: usize,
+    pub name: String,
+    pub recognized: Recognition,
+    pub recognized_name: String,
+}
+
+/// Compare the uid of every card, taken from its page and slot, against the
+/// monster recognized from its artwork.
+pub fn cross_check(pages: &[TranscribedPage], index: &CardIndex) -> Vec<Mismatch> {
+    let monsters = catalog::monsters();
+    let name = |uid: usize| {
+        monsters
+            .get(uid)
+            .map(|monster| monster.name.clone())
+            .unwrap_or_default()
+    };
+    pages
+        .iter()
+        .flat_map(|page| page.cards.iter())
+        .filter_map(|(entry, card)| {
+            let recognized = index.recognize(card)?;
+            if recognized.uid == entry.uid {
+                return None;
+            }
+            Some(Mismatch {
+                uid: entry.uid,
+                name: entry.name.clone(),
+                recognized,
+                recognized_name: name(recognized.uid),
+            })
+        })
+        .collect()
+}
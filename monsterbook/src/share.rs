@@ -0,0 +1,187 @@
+use super::catalog;
+use super::transcription::{Entry, Transcription, MAX_COUNT};
+use super::utils;
+
+// enough for counts from 0 to 7
+const BITS: usize = 3;
+const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+// url safe base64 without padding
+fn to_base64(bytes: &[u8]) -> String {
+    let mut code = String::new();
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..chunk.len() + 1 {
+            code.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+        }
+    }
+    code
+}
+
+fn from_base64(code: &str) -> Result<Vec<u8>, String> {
+    let digits = code
+        .trim()
+        .bytes()
+        .map(|c| {
+            ALPHABET
+                .iter()
+                .position(|a| *a == c)
+                .map(|digit| digit as u32)
+                .ok_or(format!("invalid character {} in share code", c as char))
+        })
+        .collect::<Result<Vec<u32>, String>>()?;
+    let mut bytes = Vec::new();
+    for chunk in digits.chunks(4) {
+        if chunk.len() == 1 {
+            return Err("share code has the wrong length".into());
+        }
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, digit)| n | digit << (18 - 6 * i));
+        for i in 0..chunk.len() - 1 {
+            bytes.push((n >> (16 - 8 * i)) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 2] {
+    (utils::crc32(bytes) as u16).to_be_bytes()
+}
+
+/// Pack the count of every card in the catalog into a url safe string. The
+/// first byte is the catalog version and the last two are a checksum.
+pub fn encode(transcription: &Transcription) -> Result<String, String> {
+    let counts = transcription.counts();
+    let cards = catalog::monsters().len();
+    if let Some(uid) = counts.keys().find(|uid| **uid >= cards) {
+        return Err(format!("card {} is not in the catalog", uid));
+    }
+    let mut bytes = vec![catalog::VERSION];
+    bytes.resize(1 + (cards * BITS).div_ceil(8), 0);
+    for uid in 0..cards {
+        let count = counts.get(&uid).copied().unwrap_or(0).min(MAX_COUNT);
+        for bit in 0..BITS {
+            if count >> bit & 1 == 1 {
+                let i = uid * BITS + bit;
+                bytes[1 + i / 8] |= 1 << (i % 8);
+            }
+        }
+    }
+    let checksum = checksum(&bytes);
+    bytes.extend(checksum);
+    Ok(to_base64(&bytes))
+}
+
+/// Unpack a share code into a transcription with an entry for every card
+/// that has been seen.
+pub fn decode(code: &str) -> Result<Transcription, String> {
+    let bytes = from_base64(code)?;
+    let monsters = catalog::monsters();
+    let packed = (monsters.len() * BITS).div_ceil(8);
+    if bytes.len() != 1 + packed + 2 {
+        return Err("share code has the wrong length".into());
+    }
+    let (body, checksum_bytes) = bytes.split_at(1 + packed);
+    if checksum(body) != checksum_bytes {
+        return Err("share code is damaged, the checksum does not match".into());
+    }
+    if body[0] != catalog::VERSION {
+        return Err(format!(
+            "share code is for catalog version {}, expected {}",
+            body[0],
+            catalog::VERSION
+        ));
+    }
+    // the bits past the last card are never set by encode
+    if (monsters.len() * BITS..packed * 8).any(|i| body[1 + i / 8] >> (i % 8) & 1 == 1) {
+        return Err("share code has counts for cards that are not in the catalog".into());
+    }
+    let mut data = Vec::new();
+    for (uid, monster) in monsters.into_iter().enumerate() {
+        let count = (0..BITS)
+            .map(|bit| {
+                let i = uid * BITS + bit;
+                (body[1 + i / 8] >> (i % 8) & 1) << bit
+            })
+            .sum::<u8>();
+        if count > MAX_COUNT {
+            return Err(format!("share code has a count of {} for {}", count, uid));
+        }
+        if count == 0 {
+            continue;
+        }
+        data.push(Entry {
+            uid,
+            name: monster.name,
+            count,
+        });
+    }
+    Ok(Transcription { data })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transcription(counts: &[(usize, u8)]) -> Transcription {
+        let monsters = catalog::monsters();
+        Transcription {
+            data: counts
+                .iter()
+                .map(|(uid, count)| Entry {
+                    uid: *uid,
+                    name: monsters
+                        .get(*uid)
+                        .map(|m| m.name.clone())
+                        .unwrap_or_default(),
+                    count: *count,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        let last = catalog::monsters().len() - 1;
+        let original = transcription(&[(0, 1), (7, 5), (8, 0), (last, 3)]);
+        let decoded = decode(&encode(&original).unwrap()).unwrap();
+        let counts: Vec<(usize, u8)> = decoded
+            .data
+            .iter()
+            .map(|entry| (entry.uid, entry.count))
+            .collect();
+        assert_eq!(counts, vec![(0, 1), (7, 5), (last, 3)]);
+    }
+
+    #[test]
+    fn cards_outside_the_catalog_are_rejected() {
+        let uid = catalog::monsters().len();
+        assert!(encode(&transcription(&[(uid, 1)])).is_err());
+    }
+
+    #[test]
+    fn damaged_codes_are_rejected() {
+        let code = encode(&transcription(&[(3, 2)])).unwrap();
+        let mut damaged = code.clone().into_bytes();
+        damaged[4] = if damaged[4] == b'A' { b'B' } else { b'A' };
+        let damaged = String::from_utf8(damaged).unwrap();
+        assert!(decode(&damaged).unwrap_err().contains("checksum"));
+        assert!(decode(&code[1..]).is_err());
+        assert!(decode(&format!("{}!", code)).is_err());
+    }
+
+    #[test]
+    fn other_versions_are_rejected() {
+        let mut bytes = from_base64(&encode(&transcription(&[(3, 2)])).unwrap()).unwrap();
+        bytes.truncate(bytes.len() - 2);
+        bytes[0] = catalog::VERSION + 1;
+        let checksum = checksum(&bytes);
+        bytes.extend(checksum);
+        assert!(decode(&to_base64(&bytes)).unwrap_err().contains("version"));
+    }
+}
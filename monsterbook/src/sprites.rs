@@ -0,0 +1,103 @@
+use super::crop::{self, Image, ARTWORK, TAG};
+use super::palette::Palette;
+use super::transcribe::TranscribedPage;
+use super::utils;
+use image::{imageops, ImageError, Rgba};
+use std::collections::VecDeque;
+
+// the largest channel difference from a background color
+const TOLERANCE: f64 = 24.0;
+// the card background is a gradient that darkens towards the edges
+const SHADE: (f64, f64) = (0.5, 1.1);
+// the black and white lines of the frame that are inside the artwork
+const FRAME: u32 = 2;
+
+// whether a pixel is a lighter or darker shade of a color
+fn shade_of(pixel: &Rgba<u8>, color: &Rgba<u8>) -> bool {
+    let total = |p: &Rgba<u8>| (0..3).map(|c| p[c] as f64).sum::<f64>();
+    let scale = total(pixel) / total(color).max(1.0);
+    (SHADE.0..=SHADE.1).contains(&scale)
+        && (0..3).all(|c| (pixel[c] as f64 - scale * color[c] as f64).abs() <= TOLERANCE)
+}
+
+fn close(a: &Rgba<u8>, b: &Rgba<u8>) -> bool {
+    (0..3).all(|c| (a[c] as f64 - b[c] as f64).abs() <= TOLERANCE)
+}
+
+/// Cut the artwork out of a card with a transparent background. Background
+/// pixels match either the tab color, when it is known, or the empty card,
+/// and are flood filled from the edge of the artwork so that matching colors
+/// inside the monster are kept.
+pub fn extract_sprite(card: &Image, empty: &Image, background: Option<Rgba<u8>>) -> Image {
+    let (x, y, w, h) = ARTWORK;
+    let mut card = card.clone();
+    let mut sprite = imageops::crop(&mut card, x, y, w, h).to_image();
+    let is_background = |i: u32, j: u32| {
+        let pixel = sprite.get_pixel(i, j);
+        matches!(background, Some(background) if shade_of(pixel, &background))
+            || close(pixel, empty.get_pixel(x + i, y + j))
+    };
+    let mut transparent = vec![false; (w * h) as usize];
+    let mut queue: VecDeque<(u32, u32)> = (0..w)
+        .flat_map(|i| (0..h).map(move |j| (i, j)))
+        .filter(|&(i, j)| i.min(w - 1 - i).min(j).min(h - 1 - j) == FRAME)
+        .collect();
+    while let Some((i, j)) = queue.pop_front() {
+        let index = (j * w + i) as usize;
+        if transparent[index] || !is_background(i, j) {
+            continue;
+        }
+        transparent[index] = true;
+        if i > 0 {
+            queue.push_back((i - 1, j));
+        }
+        if i + 1 < w {
+            queue.push_back((i + 1, j));
+        }
+        if j > 0 {
+            queue.push_back((i, j - 1));
+        }
+        if j + 1 < h {
+            queue.push_back((i, j + 1));
+        }
+    }
+    // the count tag and its outline sit on top of the artwork
+    let (tx, ty, tw, th) = TAG;
+    for (i, j, pixel) in sprite.enumerate_pixels_mut() {
+        let in_tag = (tx - 1..=tx + tw).contains(&(x + i)) && (ty - 1..=ty + th).contains(&(y + j));
+        let in_frame = i.min(w - 1 - i).min(j).min(h - 1 - j) < FRAME;
+        if transparent[(j * w + i) as usize] || in_tag || in_frame {
+            *pixel = Rgba([0, 0, 0, 0]);
+        }
+    }
+    sprite
+}
+
+/// A sprite for every seen card, keyed by uid and name.
+pub fn extract_sprites(
+    pages: &[TranscribedPage],
+    palette: &Palette,
+) -> Result<Vec<(usize, String, Image)>, ImageError> {
+    let empty = crop::get_empty_card()?;
+    let metadata = utils::page_metadata();
+    let mut sprites = Vec::new();
+    for page in pages {
+        let background = palette.color(&metadata[page.page_id].tab_color);
+        for (entry, card) in &page.cards {
+            if entry.count > 0 {
+                let sprite = extract_sprite(card, &empty, background);
+                sprites.push((entry.uid, entry.name.clone(), sprite));
+            }
+        }
+    }
+    Ok(sprites)
+}
+
+/// A file name for a sprite, such as `012_Blue_Snail.png`.
+pub fn sprite_name(uid: usize, name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    format!("{:03}_{}.png", uid, name)
+}
@@ -0,0 +1,141 @@
+use super::catalog;
+use super::transcription::{Transcription, MAX_COUNT};
+use super::utils;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Completion {
+    pub name: String,
+    /// Cards with at least one copy collected
+    pub seen: u32,
+    /// Cards with all copies collected
+    pub completed: u32,
+    pub total: u32,
+    /// Percentage of completed cards
+    pub percent: f32,
+}
+
+impl Completion {
+    fn new(name: &str) -> Self {
+        Completion {
+            name: name.into(),
+            ..Completion::default()
+        }
+    }
+
+    fn add(&mut self, count: u8) {
+        self.total += 1;
+        if count > 0 {
+            self.seen += 1;
+        }
+        if count >= MAX_COUNT {
+            self.completed += 1;
+        }
+        self.percent = 100.0 * self.completed as f32 / self.total as f32;
+    }
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Milestone {
+    pub percent: u32,
+    pub cards_needed: u32,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Stats {
+    pub tabs: Vec<Completion>,
+    pub towns: Vec<Completion>,
+    pub overall: Completion,
+    pub next_milestone: Option<Milestone>,
+}
+
+// milestones are every 10% of completed cards
+fn next_milestone(overall: &Completion) -> Option<Milestone> {
+    let step = 10;
+    (1..=100 / step)
+        .map(|i| i * step)
+        .map(|percent| Milestone {
+            percent,
+            cards_needed: (overall.total * percent + 99) / 100,
+        })
+        .find(|milestone| milestone.cards_needed > overall.completed)
+        .map(|milestone| Milestone {
+            cards_needed: milestone.cards_needed - overall.completed,
+            ..milestone
+        })
+}
+
+/// Compute completion per tab, per town and for the whole book.
+pub fn stats(transcription: &Transcription) -> Stats {
+    let counts = transcription.counts();
+    let monsters = catalog::monsters();
+    let offsets = utils::page_offsets();
+
+    let mut tabs: Vec<Completion> = Vec::new();
+    let mut towns: Vec<Completion> = Vec::new();
+    let mut town_index = HashMap::new();
+    let mut overall = Completion::new("overall");
+
+    for (metadata, window) in utils::page_metadata().iter().zip(offsets.windows(2)) {
+        if tabs.last().map(|tab| &tab.name) != Some(&metadata.tab_color) {
+            tabs.push(Completion::new(&metadata.tab_color));
+        }
+        for uid in window[0]..window[1] {
+            let count = *counts.get(&uid).unwrap_or(&0);
+            tabs.last_mut().unwrap().add(count);
+            overall.add(count);
+            if let Some(monster) = monsters.get(uid) {
+                let index = *town_index.entry(monster.town.clone()).or_insert_with(|| {
+                    towns.push(Completion::new(&monster.town));
+                    towns.len() - 1
+                });
+                towns[index].add(count);
+            }
+        }
+    }
+
+    let next_milestone = next_milestone(&overall);
+    Stats {
+        tabs,
+        towns,
+        overall,
+        next_milestone,
+    }
+}
+
+fn format_row(completion: &Completion) -> String {
+    format!(
+        "{:<32} {:>5} {:>9} {:>6} {:>7.1}%",
+        completion.name,
+        completion.seen,
+        completion.completed,
+        completion.total,
+        completion.percent
+    )
+}
+
+fn format_header(label: &str) -> String {
+    format!(
+        "{:<32} {:>5} {:>9} {:>6} {:>8}",
+        label, "seen", "completed", "total", "percent"
+    )
+}
+
+/// Render the statistics as a plain text table for the terminal.
+pub fn format_table(stats: &Stats) -> String {
+    let mut lines = vec![format_header("tab")];
+    lines.extend(stats.tabs.iter().map(format_row));
+    lines.push(String::new());
+    lines.push(format_header("town"));
+    lines.extend(stats.towns.iter().map(format_row));
+    lines.push(String::new());
+    lines.push(format_row(&stats.overall));
+    if let Some(milestone) = &stats.next_milestone {
+        lines.push(format!(
+            "next milestone: {}% completed ({} more cards)",
+            milestone.percent, milestone.cards_needed
+        ));
+    }
+    lines.join("\n")
+}
@@ -1,21 +1,255 @@
 use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+use std::str::FromStr;
 type Image = ImageBuffer<Rgba<u8>, Vec<u8>>;
 
-pub fn stitch_images(images: Vec<Image>, width: u32) -> Image {
-    let x = images[0].width();
-    let y = images[0].height();
-    let n = images.len();
+/// Where the images of a partially filled final row (or column) are placed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Alignment {
+    Start,
+    Center,
+    End,
+}
+
+impl Alignment {
+    fn offset(&self, free: u32, step: u32) -> u32 {
+        match self {
+            Alignment::Start => 0,
+            Alignment::Center => free * step / 2,
+            Alignment::End => free * step,
+        }
+    }
+}
+
+impl FromStr for Alignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "start" | "left" | "top" => Ok(Alignment::Start),
+            "center" => Ok(Alignment::Center),
+            "end" | "right" | "bottom" => Ok(Alignment::End),
+            _ => Err(format!(
+                "unknown alignment {}, expected one of start, center, end",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    RowMajor,
+    ColumnMajor,
+}
+
+/// The defaults place images edge to edge on a transparent canvas.
+#[derive(Clone, Copy, Debug)]
+pub struct StitchOptions {
+    /// Space between neighbouring images
+    pub gutter: u32,
+    /// Space around the outside of the grid
+    pub margin: u32,
+    pub border_width: u32,
+    pub border_color: Rgba<u8>,
+    pub background: Rgba<u8>,
+    pub alignment: Alignment,
+    pub order: Order,
+}
+
+impl Default for StitchOptions {
+    fn default() -> Self {
+        StitchOptions {
+            gutter: 0,
+            margin: 0,
+            border_width: 0,
+            border_color: Rgba([0, 0, 0, 255]),
+            background: Rgba([0, 0, 0, 0]),
+            alignment: Alignment::Start,
+            order: Order::RowMajor,
+        }
+    }
+}
+
+/// Caps on each stitched image, which is split into parts to stay within
+/// them. Nothing is capped by default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub max_cards: Option<usize>,
+}
+
+impl Limits {
+    pub fn is_unlimited(&self) -> bool {
+        *self == Limits::default()
+    }
+
+    pub fn fits(&self, img: &Image, cards: usize) -> bool {
+        self.fits_size(img.width(), img.height(), cards)
+    }
+
+    pub fn fits_size(&self, width: u32, height: u32, cards: usize) -> bool {
+        self.max_width.is_none_or(|max| width <= max)
+            && self.max_height.is_none_or(|max| height <= max)
+            && self.max_cards.is_none_or(|max| cards <= max)
+    }
+}
+
+/// Parse a color written as `rrggbb` or `rrggbbaa`, with an optional `#`.
+pub fn parse_color(s: &str) -> Result<Rgba<u8>, String> {
+    let hex = s.trim_start_matches('#');
+    let channel = |i: usize| {
+        hex.get(i..i + 2)
+            .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+            .ok_or(format!("invalid color {}, expected rrggbb or rrggbbaa", s))
+    };
+    match hex.len() {
+        6 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, 255])),
+        8 => Ok(Rgba([channel(0)?, channel(2)?, channel(4)?, channel(6)?])),
+        _ => Err(format!("invalid color {}, expected rrggbb or rrggbbaa", s)),
+    }
+}
+
+fn draw_border(canvas: &mut Image, x: u32, y: u32, w: u32, h: u32, options: &StitchOptions) {
+    let b = options.border_width;
+    for j in y..y + h {
+        for i in x..x + w {
+            if i < x + b || i >= x + w - b || j < y + b || j >= y + h - b {
+                canvas.put_pixel(i, j, options.border_color);
+            }
+        }
+    }
+}
+
+/// Lay images of the same size out in a grid `width` images wide. Without any
+/// images the canvas is only the margins.
+pub fn stitch_images(images: Vec<Image>, width: u32, options: &StitchOptions) -> Image {
+    let first = match images.first() {
+        Some(first) => first,
+        None => {
+            let side = 2 * options.margin;
+            return RgbaImage::from_pixel(side, side, options.background);
+        }
+    };
+    let x = first.width() + 2 * options.border_width;
+    let y = first.height() + 2 * options.border_width;
+    let n = images.len() as u32;
     let height = (n as f32 / width as f32).ceil() as u32;
-    let mut background = RgbaImage::new(x * width, y * height);
-    for i in 0..height {
-        for j in 0..width {
-            let index = (i * width + j) as usize;
-            if index >= n {
-                break;
+    let columns = (n as f32 / height as f32).ceil() as u32;
+    // filling columns first can leave the last of the `width` columns unused
+    let canvas_columns = match options.order {
+        Order::RowMajor => width,
+        Order::ColumnMajor => columns,
+    };
+    let (step_x, step_y) = (x + options.gutter, y + options.gutter);
+    let mut background = RgbaImage::from_pixel(
+        2 * options.margin + canvas_columns * step_x - options.gutter,
+        2 * options.margin + height * step_y - options.gutter,
+        options.background,
+    );
+    for (index, img) in images.iter().enumerate() {
+        let index = index as u32;
+        let (i, j, shift_x, shift_y) = match options.order {
+            Order::RowMajor => {
+                let (i, j) = (index / width, index % width);
+                let shift = if i == height - 1 {
+                    options.alignment.offset(height * width - n, step_x)
+                } else {
+                    0
+                };
+                (i, j, shift, 0)
             }
-            let img = &images[index];
-            imageops::overlay(&mut background, img, j * x, i * y);
+            Order::ColumnMajor => {
+                let (i, j) = (index % height, index / height);
+                let shift = if j == columns - 1 {
+                    options.alignment.offset(columns * height - n, step_y)
+                } else {
+                    0
+                };
+                (i, j, 0, shift)
+            }
+        };
+        let left = options.margin + j * step_x + shift_x;
+        let top = options.margin + i * step_y + shift_y;
+        if options.border_width > 0 {
+            draw_border(&mut background, left, top, x, y, options);
         }
+        let b = options.border_width;
+        imageops::overlay(&mut background, img, left + b, top + b);
     }
     background
 }
+
+/// Pack images of different sizes into rows of at most `width` pixels, in the
+/// order they are given, leaving `padding` pixels between them. Returns the
+/// packed image and the position of each image.
+pub fn pack_images(images: &[Image], width: u32, padding: u32) -> (Image, Vec<(u32, u32)>) {
+    let mut positions = Vec::new();
+    let (mut x, mut y, mut row_height, mut used_width) = (0, 0, 0, 0);
+    for img in images {
+        if x > 0 && x + img.width() > width {
+            x = 0;
+            y += row_height + padding;
+            row_height = 0;
+        }
+        positions.push((x, y));
+        x += img.width() + padding;
+        used_width = used_width.max(x - padding);
+        row_height = row_height.max(img.height());
+    }
+    let mut background = RgbaImage::new(used_width, y + row_height);
+    for (img, (x, y)) in images.iter().zip(&positions) {
+        imageops::overlay(&mut background, img, *x, *y);
+    }
+    (background, positions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_parse_with_or_without_alpha() {
+        assert_eq!(parse_color("#ff8000"), Ok(Rgba([255, 128, 0, 255])));
+        assert_eq!(parse_color("00000080"), Ok(Rgba([0, 0, 0, 128])));
+        assert!(parse_color("fff").is_err());
+        assert!(parse_color("gg0000").is_err());
+    }
+
+    #[test]
+    fn limits_check_every_bound() {
+        let img = Image::new(100, 50);
+        assert!(Limits::default().fits(&img, 1000));
+        let limits = Limits {
+            max_width: Some(100),
+            max_height: Some(50),
+            max_cards: Some(10),
+        };
+        assert!(limits.fits(&img, 10));
+        assert!(!limits.fits(&img, 11));
+        assert!(!limits.fits(&Image::new(101, 50), 1));
+        assert!(!limits.fits(&Image::new(100, 51), 1));
+    }
+
+    #[test]
+    fn column_major_canvas_fits_the_filled_columns() {
+        let images = vec![Image::new(2, 3); 7];
+        let row_major = stitch_images(images.clone(), 5, &StitchOptions::default());
+        assert_eq!(row_major.dimensions(), (10, 6));
+        let options = StitchOptions {
+            order: Order::ColumnMajor,
+            ..StitchOptions::default()
+        };
+        // two rows of seven cards fill four columns
+        assert_eq!(stitch_images(images, 5, &options).dimensions(), (8, 6));
+    }
+
+    #[test]
+    fn no_images_leave_only_the_margins() {
+        let options = StitchOptions {
+            margin: 4,
+            ..StitchOptions::default()
+        };
+        assert_eq!(stitch_images(Vec::new(), 5, &options).dimensions(), (8, 8));
+    }
+}
@@ -0,0 +1,227 @@
+use super::calibrate::Calibration;
+use super::catalog;
+use super::crop::{self, CardState, Image};
+use super::metrics::MetricKind;
+use super::occlusion::{self, Mask, Occlusion};
+use super::transcription::{Entry, Transcription};
+use super::utils;
+use image::{imageops, ImageError};
+
+// the web application assets are saved at twice the resolution of a cropped
+// windows screenshot, so they are resized on load
+const REFERENCE_PAGES: [&[u8]; 22] = [
+    include_bytes!("../../src/assets/reference/00_red_0.png"),
+    include_bytes!("../../src/assets/reference/01_orange_0.png"),
+    include_bytes!("../../src/assets/reference/02_orange_1.png"),
+    include_bytes!("../../src/assets/reference/03_orange_2.png"),
+    include_bytes!("../../src/assets/reference/04_lightgreen_0.png"),
+    include_bytes!("../../src/assets/reference/05_lightgreen_1.png"),
+    include_bytes!("../../src/assets/reference/06_lightgreen_2.png"),
+    include_bytes!("../../src/assets/reference/07_lightgreen_3.png"),
+    include_bytes!("../../src/assets/reference/08_green_0.png"),
+    include_bytes!("../../src/assets/reference/09_green_1.png"),
+    include_bytes!("../../src/assets/reference/10_green_2.png"),
+    include_bytes!("../../src/assets/reference/11_lightblue_0.png"),
+    include_bytes!("../../src/assets/reference/12_lightblue_1.png"),
+    include_bytes!("../../src/assets/reference/13_lightblue_2.png"),
+    include_bytes!("../../src/assets/reference/14_blue_0.png"),
+    include_bytes!("../../src/assets/reference/15_blue_1.png"),
+    include_bytes!("../../src/assets/reference/16_purple_0.png"),
+    include_bytes!("../../src/assets/reference/17_purple_1.png"),
+    include_bytes!("../../src/assets/reference/18_black_0.png"),
+    include_bytes!("../../src/assets/reference/19_black_1.png"),
+    include_bytes!("../../src/assets/reference/20_gold_0.png"),
+    include_bytes!("../../src/assets/reference/21_gold_1.png"),
+];
+
+const SEED_TAGS: [&[u8]; 5] = [
+    include_bytes!("../../src/assets/seed_tags/1.png"),
+    include_bytes!("../../src/assets/seed_tags/2.png"),
+    include_bytes!("../../src/assets/seed_tags/3.png"),
+    include_bytes!("../../src/assets/seed_tags/4.png"),
+    include_bytes!("../../src/assets/seed_tags/5.png"),
+];
+
+/// Number of pages that can be identified from a screenshot.
+pub fn identifiable_pages() -> usize {
+    REFERENCE_PAGES.len()
+}
+
+pub fn reference_page(page_id: usize) -> Result<Image, ImageError> {
+    let img = crop::imdecode(REFERENCE_PAGES[page_id])?;
+    Ok(imageops::resize(&img, 165, 225, imageops::FilterType::Nearest))
+}
+
+pub fn reference_pages() -> Result<Vec<Image>, ImageError> {
+    (0..REFERENCE_PAGES.len()).map(reference_page).collect()
+}
+
+/// Every card in the reference book with its uid. The cards of the last page
+/// have no reference and are left out.
+pub fn reference_cards() -> Result<Vec<(usize, Image)>, ImageError> {
+    let offsets = utils::page_offsets();
+    let metadata = utils::page_metadata();
+    let mut cards = Vec::new();
+    for page_id in 0..identifiable_pages() {
+        let page = reference_page(page_id)?;
+        let count = metadata[page_id].card_count as usize;
+        for (i, card) in crop::crop_cards(&page)?.into_iter().take(count).enumerate() {
+            cards.push((offsets[page_id] + i, card));
+        }
+    }
+    Ok(cards)
+}
+
+pub fn seed_tags() -> Result<Vec<Image>, ImageError> {
+    SEED_TAGS
+        .iter()
+        .map(|bytes| {
+            let img = crop::imdecode(bytes)?;
+            Ok(imageops::resize(&img, 6, 9, imageops::FilterType::Nearest))
+        })
+        .collect()
+}
+
+/// Return the index of the closest reference image and its distance.
+pub fn best_match_distance(img: &Image, references: &[Image], metric: MetricKind) -> (usize, u32) {
+    references
+        .iter()
+        .map(|reference| metric.distance(img, reference))
+        .enumerate()
+        .min_by_key(|(_, diff)| *diff)
+        .unwrap()
+}
+
+/// Return the index of the closest reference image.
+pub fn best_match(img: &Image, references: &[Image], metric: MetricKind) -> usize {
+    best_match_distance(img, references, metric).0
+}
+
+// the count in the lower left of the card
+pub fn crop_tag(card: &Image) -> Image {
+    let (x, y, w, h) = crop::TAG;
+    let mut card = card.clone();
+    imageops::crop(&mut card, x, y, w, h).to_image()
+}
+
+pub struct TranscribedPage {
+    pub page_id: usize,
+    pub cards: Vec<(Entry, Image)>,
+    /// The slot on the page of each card, skipping empty cards
+    pub slots: Vec<usize>,
+    /// Cards where the count tag was not close to any of the seed tags, or
+    /// was hidden under the cursor or a tooltip
+    pub uncertain: Vec<usize>,
+    /// Slots on the page that are covered and should be captured again
+    pub occluded: Vec<(usize, Occlusion)>,
+}
+
+/// Pick the closest reference page, ignoring the masked pixels.
+fn identify_page(page: &Image, references: &[Image], metric: MetricKind, mask: &Mask) -> usize {
+    references
+        .iter()
+        .map(|reference| occlusion::masked_distance(metric, page, reference, mask))
+        .enumerate()
+        .min_by_key(|(_, distance)| *distance)
+        .map(|(page_id, _)| page_id)
+        .unwrap()
+}
+
+/// Identify a cropped page and read the count of each card on it.
+pub fn transcribe_page(
+    page: &Image,
+    references: &[Image],
+    tags: &[Image],
+    calibration: &Calibration,
+) -> Result<TranscribedPage, ImageError> {
+    let monsters = catalog::monsters();
+    // comparisons run on the denoised page, while the cards keep the original pixels
+    let compared = calibration.compared(page);
+    let occlusions = occlusion::detect_page(&compared)?;
+    let mask = occlusion::page_mask(&compared, &occlusions);
+    let page_id = identify_page(&compared, references, calibration.page_metric, &mask);
+    let offset = utils::page_offsets()[page_id];
+    let mut uncertain = Vec::new();
+    let (slots, cards) = crop::crop_cards(&compared)?
+        .into_iter()
+        .zip(crop::crop_cards(page)?)
+        .zip(&occlusions)
+        .enumerate()
+        .map(|(slot, ((card, original), occluded))| {
+            // nothing under a tooltip can be trusted, so compare the whole card
+            let state = match occluded {
+                Some(occluded) if occluded.kind == Occlusion::Cursor => {
+                    calibration
+                        .cards
+                        .classify_masked(&card, &occluded.mask)
+                        .state
+                }
+                _ => calibration.cards.classify(&card).state,
+            };
+            (slot, state, card, original, occluded)
+        })
+        .filter(|(_, state, _, _, _)| *state != CardState::Empty)
+        .enumerate()
+        .map(|(i, (slot, state, card, original, occluded))| {
+            let uid = offset + i;
+            let mut count = 0;
+            if matches!(occluded, Some(occluded) if occluded.covers_tag()) {
+                uncertain.push(uid);
+            }
+            if state == CardState::Seen {
+                let (index, distance) =
+                    best_match_distance(&crop_tag(&card), tags, calibration.tag_metric);
+                count = index as u8 + 1;
+                if matches!(calibration.tag, Some(tag) if distance > tag) {
+                    uncertain.push(uid);
+                }
+            }
+            let name = monsters
+                .get(uid)
+                .map(|monster| monster.name.clone())
+                .unwrap_or_default();
+            (slot, (Entry { uid, name, count }, original))
+        })
+        .unzip();
+    let occluded = occlusions
+        .iter()
+        .enumerate()
+        .filter_map(|(i, occluded)| occluded.as_ref().map(|occluded| (i, occluded.kind)))
+        .collect();
+    Ok(TranscribedPage {
+        page_id,
+        cards,
+        slots,
+        uncertain,
+        occluded,
+    })
+}
+
+pub fn transcribe_pages(
+    images: &[Image],
+    calibration: &Calibration,
+) -> Result<Vec<TranscribedPage>, ImageError> {
+    let references = reference_pages()?;
+    let tags = seed_tags()?;
+    images
+        .iter()
+        .map(|img| transcribe_page(img, &references, &tags, calibration))
+        .collect()
+}
+
+pub fn into_transcription(pages: &[TranscribedPage]) -> Transcription {
+    let mut data: Vec<Entry> = pages
+        .iter()
+        .flat_map(|page| page.cards.iter().map(|(entry, _)| entry.clone()))
+        .collect();
+    data.sort_by_key(|entry| entry.uid);
+    Transcription { data }
+}
+
+/// Transcribe a set of cropped pages, ordered by uid.
+pub fn transcribe(
+    images: &[Image],
+    calibration: &Calibration,
+) -> Result<Transcription, ImageError> {
+    Ok(into_transcription(&transcribe_pages(images, calibration)?))
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+pub const MAX_COUNT: u8 = 5;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub uid: usize,
+    pub name: String,
+    pub count: u8,
+}
+
+/// A transcribed monsterbook, in the same format as `python -m python transcribe`.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Transcription {
+    pub data: Vec<Entry>,
+}
+
+impl Transcription {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_reader(File::open(path)?)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        Ok(serde_json::to_writer_pretty(File::create(path)?, self)?)
+    }
+
+    /// Card counts keyed by uid. Cards that were never transcribed are absent.
+    pub fn counts(&self) -> HashMap<usize, u8> {
+        self.data.iter().map(|entry| (entry.uid, entry.count)).collect()
+    }
+}
@@ -0,0 +1,236 @@
+use super::crop::Image;
+use image::{imageops, Rgba, RgbaImage};
+use std::str::FromStr;
+
+// channels this close are treated as the same color, so that edges survive
+// the slight noise of a screenshot
+const SIMILAR: i32 = 12;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scaler {
+    #[default]
+    Nearest,
+    /// Scale2x and Scale3x, which round off the corners of diagonal edges
+    Scale2x,
+}
+
+impl FromStr for Scaler {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Scaler::Nearest),
+            "scale2x" => Ok(Scaler::Scale2x),
+            _ => Err(format!(
+                "unknown scaler {}, expected one of nearest, scale2x",
+                s
+            )),
+        }
+    }
+}
+
+/// An integer upscale. The default leaves images as they are.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Upscale {
+    pub factor: u32,
+    pub scaler: Scaler,
+}
+
+impl Default for Upscale {
+    fn default() -> Self {
+        Upscale {
+            factor: 1,
+            scaler: Scaler::Nearest,
+        }
+    }
+}
+
+/// Parse a scale factor, which has to be at least one.
+pub fn parse_factor(s: &str) -> Result<u32, String> {
+    match s.parse::<u32>() {
+        Ok(0) => Err("the scale has to be at least 1".into()),
+        Ok(factor) => Ok(factor),
+        Err(_) => Err(format!("invalid scale {}", s)),
+    }
+}
+
+fn same(a: Rgba<u8>, b: Rgba<u8>) -> bool {
+    (0..4).all(|c| (a[c] as i32 - b[c] as i32).abs() <= SIMILAR)
+}
+
+// the pixel at an offset, clamped to the edge of the image
+fn at(img: &Image, x: u32, y: u32, dx: i32, dy: i32) -> Rgba<u8> {
+    let x = (x as i32 + dx).clamp(0, img.width() as i32 - 1) as u32;
+    let y = (y as i32 + dy).clamp(0, img.height() as i32 - 1) as u32;
+    *img.get_pixel(x, y)
+}
+
+pub fn scale2x(img: &Image) -> Image {
+    let mut out = RgbaImage::new(img.width() * 2, img.height() * 2);
+    for (x, y, &e) in img.enumerate_pixels() {
+        let (b, d, f, h) = (
+            at(img, x, y, 0, -1),
+            at(img, x, y, -1, 0),
+            at(img, x, y, 1, 0),
+            at(img, x, y, 0, 1),
+        );
+        let mut block = [e; 4];
+        if !same(b, h) && !same(d, f) {
+            if same(d, b) {
+                block[0] = d;
+            }
+            if same(b, f) {
+                block[1] = f;
+            }
+            if same(d, h) {
+                block[2] = d;
+            }
+            if same(h, f) {
+                block[3] = f;
+            }
+        }
+        for (i, pixel) in block.iter().enumerate() {
+            out.put_pixel(2 * x + i as u32 % 2, 2 * y + i as u32 / 2, *pixel);
+        }
+    }
+    out
+}
+
+pub fn scale3x(img: &Image) -> Image {
+    let mut out = RgbaImage::new(img.width() * 3, img.height() * 3);
+    for (x, y, &e) in img.enumerate_pixels() {
+        let p = |dx, dy| at(img, x, y, dx, dy);
+        let (a, b, c) = (p(-1, -1), p(0, -1), p(1, -1));
+        let (d, f) = (p(-1, 0), p(1, 0));
+        let (g, h, i) = (p(-1, 1), p(0, 1), p(1, 1));
+        let mut block = [e; 9];
+        if !same(b, h) && !same(d, f) {
+            if same(d, b) {
+                block[0] = d;
+            }
+            if (same(d, b) && !same(e, c)) || (same(b, f) && !same(e, a)) {
+                block[1] = b;
+            }
+            if same(b, f) {
+                block[2] = f;
+            }
+            if (same(d, b) && !same(e, g)) || (same(d, h) && !same(e, a)) {
+                block[3] = d;
+            }
+            if (same(b, f) && !same(e, i)) || (same(h, f) && !same(e, c)) {
+                block[5] = f;
+            }
+            if same(d, h) {
+                block[6] = d;
+            }
+            if (same(d, h) && !same(e, i)) || (same(h, f) && !same(e, g)) {
+                block[7] = h;
+            }
+            if same(h, f) {
+                block[8] = f;
+            }
+        }
+        for (k, pixel) in block.iter().enumerate() {
+            out.put_pixel(3 * x + k as u32 % 3, 3 * y + k as u32 / 3, *pixel);
+        }
+    }
+    out
+}
+
+/// Scale an image up by a whole factor. Scale2x handles the factors of two
+/// and three, and whatever is left over is scaled with nearest neighbor.
+pub fn upscale(img: &Image, upscale: &Upscale) -> Image {
+    let mut factor = upscale.factor.max(1);
+    let mut img = img.clone();
+    if upscale.scaler == Scaler::Scale2x {
+        while factor.is_multiple_of(2) {
+            img = scale2x(&img);
+            factor /= 2;
+        }
+        while factor.is_multiple_of(3) {
+            img = scale3x(&img);
+            factor /= 3;
+        }
+    }
+    if factor > 1 {
+        img = imageops::resize(
+            &img,
+            img.width() * factor,
+            img.height() * factor,
+            imageops::FilterType::Nearest,
+        );
+    }
+    img
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const B: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const W: Rgba<u8> = Rgba([255, 255, 255, 255]);
+
+    // a black corner against white, so the edge runs diagonally
+    fn corner() -> Image {
+        let mut img = RgbaImage::from_pixel(2, 2, W);
+        img.put_pixel(0, 0, B);
+        img
+    }
+
+    fn nearest(img: &Image, factor: u32) -> Image {
+        upscale(
+            img,
+            &Upscale {
+                factor,
+                scaler: Scaler::Nearest,
+            },
+        )
+    }
+
+    #[test]
+    fn scale2x_rounds_off_the_corner() {
+        let out = scale2x(&corner());
+        assert_eq!(out.dimensions(), (4, 4));
+        for (x, y, pixel) in out.enumerate_pixels() {
+            let black = x + y < 2;
+            assert_eq!(*pixel, if black { B } else { W }, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn scale3x_rounds_off_the_corner() {
+        let out = scale3x(&corner());
+        assert_eq!(out.dimensions(), (6, 6));
+        for (x, y, pixel) in out.enumerate_pixels() {
+            let black = x < 3 && y < 3 && x + y < 3;
+            assert_eq!(*pixel, if black { B } else { W }, "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn factors_split_into_twos_threes_and_nearest() {
+        let img = corner();
+        let scaled = |factor| {
+            upscale(
+                &img,
+                &Upscale {
+                    factor,
+                    scaler: Scaler::Scale2x,
+                },
+            )
+        };
+        assert_eq!(scaled(1), img);
+        assert_eq!(scaled(4), scale2x(&scale2x(&img)));
+        assert_eq!(scaled(6), scale3x(&scale2x(&img)));
+        assert_eq!(scaled(5), nearest(&img, 5));
+        assert_eq!(scaled(10), nearest(&scale2x(&img), 5));
+        assert_ne!(nearest(&img, 2), scale2x(&img));
+    }
+
+    #[test]
+    fn factors_have_to_be_positive() {
+        assert_eq!(parse_factor("3"), Ok(3));
+        assert!(parse_factor("0").is_err());
+        assert!(parse_factor("x").is_err());
+    }
+}
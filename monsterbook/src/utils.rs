@@ -1,14 +1,22 @@
+use super::arrange::{self, Arrangement};
+use super::calibrate::Calibration;
+use super::catalog;
 use super::crop;
 use super::crop::Image;
-use super::stitch;
-use image::{ImageError, Rgba};
+use super::labels::{self, Caption, Labels};
+use super::overlay::{self, Overlay};
+use super::palette::Palette;
+use super::stitch::{self, Limits, StitchOptions};
+use super::upscale::{self, Upscale};
+use image::ImageError;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 pub struct PageMetadata {
     pub page_id: u8,
     pub tab_color: String,
     pub tab_index: u8,
+    pub card_count: u8,
 }
 
 pub fn page_metadata() -> Vec<PageMetadata> {
@@ -23,6 +31,19 @@ pub fn page_metadata() -> Vec<PageMetadata> {
         ("black", 2),
         ("gold", 3),
     ];
+    // matches the catalog, the last gold page has no cards in it yet
+    #[rustfmt::skip]
+    const PAGE_COUNTS: [u8; 23] = [
+        13,
+        25, 25, 1,
+        25, 25, 25, 10,
+        25, 25, 20,
+        25, 25, 1,
+        25, 6,
+        25, 8,
+        25, 5,
+        25, 25, 0,
+    ];
     let mut meta = Vec::new();
     let mut page_id = 0;
     for (color, count) in TAB_COUNTS {
@@ -31,6 +52,7 @@ pub fn page_metadata() -> Vec<PageMetadata> {
                 page_id: page_id,
                 tab_color: color.into(),
                 tab_index: i,
+                card_count: PAGE_COUNTS[page_id as usize],
             });
             page_id += 1;
         }
@@ -38,38 +60,59 @@ pub fn page_metadata() -> Vec<PageMetadata> {
     return meta;
 }
 
-pub fn get_color(color: &str) -> Rgba<u8> {
-    match color {
-        "red" => Rgba([255, 102, 102, 255]),
-        "orange" => Rgba([255, 187, 68, 255]),
-        "lightgreen" => Rgba([221, 255, 102, 255]),
-        "green" => Rgba([102, 255, 136, 255]),
-        "lightblue" => Rgba([136, 255, 238, 255]),
-        "blue" => Rgba([119, 187, 255, 255]),
-        "purple" => Rgba([187, 119, 255, 255]),
-        "black" => Rgba([85, 85, 85, 255]),
-        "gold" => Rgba([255, 187, 34, 255]),
-        _ => Rgba([0, 0, 0, 0]),
+/// The uid of the first card on each page, with the total card count at the end.
+pub fn page_offsets() -> Vec<usize> {
+    let mut offsets = vec![0];
+    for metadata in page_metadata() {
+        offsets.push(offsets.last().unwrap() + metadata.card_count as usize);
     }
+    offsets
 }
 
-pub fn get_cropped_images(source: &Path) -> Result<Vec<Image>, ImageError> {
+/// The page that a card belongs to.
+pub fn page_of(uid: usize) -> Option<usize> {
+    page_offsets()
+        .windows(2)
+        .position(|window| window[0] <= uid && uid < window[1])
+}
+
+/// The tab colors from top to bottom.
+pub fn tab_colors() -> Vec<String> {
+    let mut colors: Vec<String> = Vec::new();
+    for metadata in page_metadata() {
+        if colors.last() != Some(&metadata.tab_color) {
+            colors.push(metadata.tab_color);
+        }
+    }
+    colors
+}
+
+/// Crop every screenshot in a directory, keeping track of the source file.
+pub fn get_cropped_images_with_paths(source: &Path) -> Result<Vec<(PathBuf, Image)>, ImageError> {
     let mut images = Vec::new();
     // output is a file
     let (mut x, mut y) = (0, 0);
     for entry in fs::read_dir(source)? {
-        let mut img = crop::imread(&entry?.path())?;
+        let path = entry?.path();
+        let mut img = crop::imread(&path)?;
         if x == 0 && y == 0 {
             let (a, b) = crop::match_reference_page(&img)?;
             x = a;
             y = b;
         }
         let cropped = crop::crop(&mut img, x, y)?;
-        images.push(cropped);
+        images.push((path, cropped));
     }
     Ok(images)
 }
 
+pub fn get_cropped_images(source: &Path) -> Result<Vec<Image>, ImageError> {
+    Ok(get_cropped_images_with_paths(source)?
+        .into_iter()
+        .map(|(_, img)| img)
+        .collect())
+}
+
 pub fn get_empty_card_mse(images: &mut Vec<Image>) -> Vec<u32> {
     images
         .iter_mut()
@@ -78,25 +121,305 @@ pub fn get_empty_card_mse(images: &mut Vec<Image>) -> Vec<u32> {
         .collect()
 }
 
-pub fn stitch_cards(images: &Vec<Image>, width: u32) -> Image {
+/// How cards look and are ordered when stitched, on top of the grid layout.
+#[derive(Clone, Debug, Default)]
+pub struct CardStyle {
+    pub palette: Palette,
+    pub labels: Labels,
+    pub overlay: Overlay,
+    pub arrangement: Arrangement,
+    pub upscale: Upscale,
+}
+
+/// Stitch each section on its own and stack them, unless there is only the
+/// single unnamed section of `GroupBy::None`.
+pub fn stitch_sections(
+    mut sections: Vec<(String, Vec<Image>)>,
+    width: u32,
+    options: &StitchOptions,
+    headings: bool,
+) -> Image {
+    if sections.len() == 1 && sections[0].0.is_empty() {
+        return stitch::stitch_images(sections.remove(0).1, width, options);
+    }
+    let sections: Vec<Image> = sections
+        .into_iter()
+        .map(|(name, section)| {
+            let stitched = stitch::stitch_images(section, width, options);
+            if headings {
+                labels::with_heading(&stitched, &name, options.background)
+            } else {
+                stitched
+            }
+        })
+        .collect();
+    labels::stack(&sections, options.gutter, options.background)
+}
+
+/// Split sections into parts that each fit within the limits once rendered,
+/// filling parts row by row. Rows are narrowed until one fits the width, and
+/// a row or section heading that still does not fit gets a part of its own.
+pub fn split_sections<F>(
+    sections: Vec<(String, Vec<Image>)>,
+    width: u32,
+    limits: &Limits,
+    render: F,
+) -> Vec<Image>
+where
+    F: Fn(Vec<(String, Vec<Image>)>, u32) -> Image,
+{
+    if limits.is_unlimited() {
+        return vec![render(sections, width)];
+    }
+    let mut width = width.min(limits.max_cards.unwrap_or(usize::MAX).max(1) as u32);
+    if let Some(card) = sections.iter().flat_map(|(_, section)| section).next() {
+        while width > 1 {
+            let row = render(
+                vec![(String::new(), vec![card.clone(); width as usize])],
+                width,
+            );
+            if limits.max_width.is_none_or(|max| row.width() <= max) {
+                break;
+            }
+            width -= 1;
+        }
+    }
+    let (first_name, card) = match sections
+        .iter()
+        .find_map(|(name, section)| section.first().map(|card| (name.clone(), card.clone())))
+    {
+        Some(first) => first,
+        None => return vec![render(Vec::new(), width)],
+    };
+    // every row has the same size, so the size of a part follows from a row on
+    // its own, a second row in the same section and a row in a second section
+    let row = vec![card.clone(); width as usize];
+    let one = render(vec![(first_name.clone(), row.clone())], width);
+    let two_rows = render(
+        vec![(first_name.clone(), [row.clone(), row.clone()].concat())],
+        width,
+    );
+    let two_sections = render(
+        vec![(first_name.clone(), row.clone()), (first_name, row)],
+        width,
+    );
+    let row_step = two_rows.height() - one.height();
+    let section_step = two_sections.height() - one.height();
+    let height = |rows: u32, sections: u32| {
+        one.height() + (rows - sections) * row_step + (sections - 1) * section_step
+    };
+    let mut parts = Vec::new();
+    let mut current: Vec<(String, Vec<Image>)> = Vec::new();
+    let (mut rows, mut cards, mut part_width) = (0, 0, 0);
+    for (name, section) in sections {
+        // a heading can be wider than the rows below it
+        let section_width = render(vec![(name.clone(), vec![card.clone()])], width)
+            .width()
+            .max(one.width());
+        for row in section.chunks(width as usize) {
+            let same_section = matches!(current.last(), Some((last, _)) if *last == name);
+            let count = current.len() as u32 + if same_section { 0 } else { 1 };
+            let fits = limits.fits_size(
+                part_width.max(section_width),
+                height(rows + 1, count),
+                cards + row.len(),
+            );
+            if !current.is_empty() && !fits {
+                parts.push(render(std::mem::take(&mut current), width));
+                rows = 0;
+                cards = 0;
+                part_width = 0;
+            }
+            match current.last_mut() {
+                Some((last, images)) if *last == name => images.extend_from_slice(row),
+                _ => current.push((name.clone(), row.to_vec())),
+            }
+            rows += 1;
+            cards += row.len();
+            part_width = part_width.max(section_width);
+        }
+    }
+    parts.push(render(current, width));
+    parts
+}
+
+/// The file for one of several parts, numbered from 1 before the extension.
+/// A single part keeps the original name.
+pub fn part_path(output: &Path, index: usize, total: usize) -> PathBuf {
+    if total <= 1 {
+        return output.to_path_buf();
+    }
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match output.extension() {
+        Some(extension) => format!("{}_{}.{}", stem, index + 1, extension.to_string_lossy()),
+        None => format!("{}_{}", stem, index + 1),
+    };
+    output.with_file_name(name)
+}
+
+/// The crc32 checksum used by png.
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+pub fn stitch_cards(
+    images: &[Image],
+    width: u32,
+    calibration: &Calibration,
+    options: &StitchOptions,
+    limits: &Limits,
+    style: &CardStyle,
+) -> Vec<Image> {
+    let CardStyle {
+        palette,
+        labels,
+        overlay,
+        arrangement,
+        upscale,
+    } = style;
+    let monsters = catalog::monsters();
+    let offsets = page_offsets();
     // now lets crop, remove all the empty entries
-    let cards = images
+    let cards: Vec<(usize, Image)> = images
         .iter()
         .zip(page_metadata().into_iter())
         .flat_map(|(img, meta)| {
+            // classify the page as it was calibrated, but keep the original pixels
+            let compared = crop::crop_cards(&calibration.compared(img)).unwrap();
             crop::crop_cards(img)
                 .unwrap()
                 .into_iter()
-                .map(|card| (card, meta.tab_color.clone()))
-                .collect::<Vec<(crop::Image, String)>>()
+                .zip(compared)
+                // to determine the threshold, generate stats and look for an obvious cutoff
+                .filter(|(_, compared)| {
+                    calibration.cards.classify(compared).state != crop::CardState::Empty
+                })
+                .enumerate()
+                .map(|(i, (card, _))| {
+                    (
+                        meta.tab_color.clone(),
+                        offsets[meta.page_id as usize] + i,
+                        card,
+                    )
+                })
+                .collect::<Vec<(String, usize, Image)>>()
         })
-        // to determine the threshold, generate stats and look for an obvious cutoff
-        .filter(|(img, _)| crop::card_mse(img) > 500)
-        .map(|(mut img, color)| {
-            crop::replace_background(&mut img, get_color(&color));
-            img
+        .map(|(color, uid, mut img)| {
+            // cards from tabs missing from the palette keep their background
+            if let Some(color) = palette.color(&color) {
+                crop::replace_background(&mut img, color);
+            }
+            overlay::apply(&mut img, uid, overlay);
+            (uid, upscale::upscale(&img, upscale))
+        })
+        .collect();
+    let caption = |uid: usize| match labels.caption {
+        Some(Caption::Name) => Some(
+            monsters
+                .get(uid)
+                .map(|monster| monster.name.clone())
+                .unwrap_or_default(),
+        ),
+        Some(Caption::Uid) => Some(uid.to_string()),
+        None => None,
+    };
+    // every caption gets the width of the longest, so the cards stay the same size
+    let caption_width = cards
+        .iter()
+        .filter_map(|(uid, _)| caption(*uid))
+        .map(|text| labels::caption_width(&text))
+        .max()
+        .unwrap_or(0);
+    let cards = cards
+        .into_iter()
+        .map(|(uid, img)| match caption(uid) {
+            Some(text) => {
+                let img = labels::with_caption(&img, &text, caption_width, options.background);
+                (uid, img)
+            }
+            None => (uid, img),
         })
         .collect();
+    let sections = arrange::arrange(cards, arrangement, overlay.counts.as_ref());
+    let parts = split_sections(sections, width, limits, |sections, width| {
+        let stitched = stitch_sections(sections, width, options, labels.headings);
+        labels::with_header(&stitched, &labels.header, options.background)
+    });
     println!("stitched cards");
-    stitch::stitch_images(cards, width)
+    parts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_counts_match_catalog() {
+        assert_eq!(*page_offsets().last().unwrap(), catalog::monsters().len());
+    }
+
+    #[test]
+    fn part_paths_are_numbered() {
+        let output = Path::new("out/book.png");
+        assert_eq!(part_path(output, 0, 1), output);
+        assert_eq!(part_path(output, 0, 3), Path::new("out/book_1.png"));
+        assert_eq!(part_path(output, 2, 3), Path::new("out/book_3.png"));
+        assert_eq!(part_path(Path::new("book"), 1, 2), Path::new("book_2"));
+    }
+
+    #[test]
+    fn parts_stay_within_limits() {
+        let card = Image::new(10, 10);
+        let sections = vec![
+            ("a".to_string(), vec![card.clone(); 7]),
+            ("b".to_string(), vec![card; 5]),
+        ];
+        let options = StitchOptions::default();
+        let limits = Limits {
+            max_width: Some(35),
+            max_height: Some(50),
+            max_cards: None,
+        };
+        let parts = split_sections(sections, 4, &limits, |sections, width| {
+            stitch_sections(sections, width, &options, true)
+        });
+        assert!(parts.len() > 1);
+        for part in &parts {
+            assert!(limits.fits(part, 0), "{:?}", part.dimensions());
+        }
+    }
+
+    #[test]
+    fn wide_headings_get_parts_of_their_own() {
+        let card = Image::new(10, 10);
+        let long = "a heading much wider than a row".to_string();
+        let sections = vec![
+            ("a".to_string(), vec![card.clone(); 3]),
+            (long.clone(), vec![card.clone(); 3]),
+            ("b".to_string(), vec![card; 3]),
+        ];
+        let options = StitchOptions::default();
+        let limits = Limits {
+            max_width: Some(40),
+            max_height: None,
+            max_cards: None,
+        };
+        let render = |sections, width| stitch_sections(sections, width, &options, true);
+        assert!(render(vec![(long, Vec::new())], 3).width() > 40);
+        let parts = split_sections(sections, 3, &limits, render);
+        assert_eq!(parts.len(), 3);
+        assert!(limits.fits(&parts[0], 0));
+        assert!(limits.fits(&parts[2], 0));
+    }
 }
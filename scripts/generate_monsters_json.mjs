@@ -0,0 +1,5 @@
+// generate the monster catalog embedded in the rust crate
+// usage: node scripts/generate_monsters_json.mjs > monsterbook/src/assets/monsters.json
+import { monsters } from "../src/monsters.js";
+
+console.log(JSON.stringify(monsters, null, 2));